@@ -0,0 +1,64 @@
+//! Times both backends on the same programs: `cargo bench`.
+//!
+//! Programs are parsed once up front, so only compiling and running them is
+//! timed. Each one is run a few times and the fastest run is reported, which
+//! is the least disturbed by whatever else the machine is doing.
+//!
+//! The VM is about three times as fast on `fib.lox` and `lookups.lox`, which
+//! spend their time in calls and loops. `asdf.lox` is 33,000 top-level
+//! `var a = 1;` statements that each run once, so the VM has to compile all
+//! of it for a single pass, and compiling a statement costs about as much as
+//! walking it does. Both backends take about 2ms on it, so the VM doesn't
+//! make straight-line code like that any faster.
+//!
+//! This only compares the backends with each other. What interning strings
+//! and resolving identifiers to symbols did for `lookups.lox` was measured
+//! with release builds from just before and just after that change, running
+//! `rlox benches/lookups.lox` a few times and keeping the fastest run: the
+//! tree-walker went from about 210ms to 160ms, and the VM from about 90ms to
+//! 80ms.
+
+use rlox::{tokenize, Backend, Interpreter, Parser, Permissions};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const RUNS: usize = 10;
+
+/// The programs to time, relative to the crate root.
+const PROGRAMS: [&str; 3] = ["asdf.lox", "benches/fib.lox", "benches/lookups.lox"];
+
+fn time(source: &str, backend: Backend) -> Duration {
+    let statements = Parser::new(source, tokenize(source, 0))
+        .parse()
+        .expect("benchmarks should parse");
+
+    (0..RUNS)
+        .map(|_| {
+            let mut interpreter = Interpreter::new(source, Permissions::none());
+            interpreter.set_backend(backend).unwrap();
+
+            let start = Instant::now();
+            interpreter
+                .interpret(&statements)
+                .expect("benchmarks should run");
+            start.elapsed()
+        })
+        .min()
+        .unwrap_or_default()
+}
+
+fn main() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+
+    for program in PROGRAMS {
+        let source = std::fs::read_to_string(root.join(program))
+            .unwrap_or_else(|e| panic!("couldn't read {program}: {e}"));
+
+        let tree = time(&source, Backend::Tree);
+        let vm = time(&source, Backend::Vm);
+        println!(
+            "{program:<20} tree {tree:>10.2?}  vm {vm:>10.2?}  ({:.1}x)",
+            tree.as_secs_f64() / vm.as_secs_f64()
+        );
+    }
+}
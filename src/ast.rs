@@ -1,23 +1,38 @@
-#![allow(dead_code)]
-#![allow(unused_variables)]
 use crate::error::*;
-use crate::tokens::{Object, Token};
+use crate::interner::Symbol;
+use crate::tokens::{Object, Span, Token};
+use std::rc::Rc;
+
+/// The name of a variable, property, function or class, interned when it is
+/// parsed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Identifier {
+    pub symbol: Symbol,
+    pub span: Span,
+}
 
 // expressions
 
 #[derive(Debug)]
 pub enum Expr {
+    Assign(AssignExpr),
     Binary(BinaryExpr),
+    Call(CallExpr),
+    Get(GetExpr),
     Grouping(GroupingExpr),
     Literal(LiteralExpr),
+    Logical(LogicalExpr),
+    Set(SetExpr),
+    Super(SuperExpr),
+    This(ThisExpr),
     Unary(UnaryExpr),
     Variable(VariableExpr),
 }
 
 #[derive(Debug)]
-pub enum BinaryOperation {
-    Addition,
-    Subtraction,
+pub struct AssignExpr {
+    pub name: Identifier,
+    pub value: Box<Expr>,
 }
 
 #[derive(Debug)]
@@ -27,6 +42,20 @@ pub struct BinaryExpr {
     pub right: Box<Expr>,
 }
 
+#[derive(Debug)]
+pub struct CallExpr {
+    pub callee: Box<Expr>,
+    /// The closing paren, used to report errors that happen during the call.
+    pub paren: Token,
+    pub arguments: Vec<Expr>,
+}
+
+#[derive(Debug)]
+pub struct GetExpr {
+    pub object: Box<Expr>,
+    pub name: Identifier,
+}
+
 #[derive(Debug)]
 pub struct GroupingExpr {
     pub expression: Box<Expr>,
@@ -35,6 +64,32 @@ pub struct GroupingExpr {
 #[derive(Debug)]
 pub struct LiteralExpr {
     pub value: Object,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub struct LogicalExpr {
+    pub left: Box<Expr>,
+    pub operator: Token,
+    pub right: Box<Expr>,
+}
+
+#[derive(Debug)]
+pub struct SetExpr {
+    pub object: Box<Expr>,
+    pub name: Identifier,
+    pub value: Box<Expr>,
+}
+
+#[derive(Debug)]
+pub struct SuperExpr {
+    pub keyword: Token,
+    pub method: Identifier,
+}
+
+#[derive(Debug)]
+pub struct ThisExpr {
+    pub keyword: Token,
 }
 
 #[derive(Debug)]
@@ -45,7 +100,7 @@ pub struct UnaryExpr {
 
 #[derive(Debug)]
 pub struct VariableExpr {
-    pub name: Token,
+    pub name: Identifier,
 }
 
 impl Expr {
@@ -54,19 +109,55 @@ impl Expr {
         use Expr::*;
 
         match self {
+            Assign(args) => visitor.visit_assign_expr(args),
             Literal(args) => visitor.visit_literal_expr(args),
+            Logical(args) => visitor.visit_logical_expr(args),
+            Set(args) => visitor.visit_set_expr(args),
+            Super(args) => visitor.visit_super_expr(args),
+            This(args) => visitor.visit_this_expr(args),
             Unary(args) => visitor.visit_unary_expr(args),
             Binary(args) => visitor.visit_binary_expr(args),
+            Call(args) => visitor.visit_call_expr(args),
+            Get(args) => visitor.visit_get_expr(args),
             Grouping(args) => visitor.visit_grouping_expr(args),
             Variable(args) => visitor.visit_variable_expr(args),
         }
     }
 }
 
+impl Expr {
+    /// The span of source code this expression was parsed from.
+    pub fn span(&self) -> Span {
+        use Expr::*;
+
+        match self {
+            Assign(args) => args.name.span.join(args.value.span()),
+            Binary(args) => args.left.span().join(args.right.span()),
+            Call(args) => args.callee.span().join(args.paren.span),
+            Get(args) => args.object.span().join(args.name.span),
+            Grouping(args) => args.expression.span(),
+            Literal(args) => args.span,
+            Logical(args) => args.left.span().join(args.right.span()),
+            Set(args) => args.object.span().join(args.value.span()),
+            Super(args) => args.keyword.span.join(args.method.span),
+            This(args) => args.keyword.span,
+            Unary(args) => args.operator.span.join(args.right.span()),
+            Variable(args) => args.name.span,
+        }
+    }
+}
+
 pub trait ExprVisitor<T> {
+    fn visit_assign_expr(&mut self, assign: &AssignExpr) -> Result<T, LoxError>;
     fn visit_literal_expr(&mut self, literal: &LiteralExpr) -> Result<T, LoxError>;
+    fn visit_logical_expr(&mut self, logical: &LogicalExpr) -> Result<T, LoxError>;
+    fn visit_set_expr(&mut self, set: &SetExpr) -> Result<T, LoxError>;
+    fn visit_super_expr(&mut self, sup: &SuperExpr) -> Result<T, LoxError>;
+    fn visit_this_expr(&mut self, this: &ThisExpr) -> Result<T, LoxError>;
     fn visit_unary_expr(&mut self, unary: &UnaryExpr) -> Result<T, LoxError>;
     fn visit_binary_expr(&mut self, binary: &BinaryExpr) -> Result<T, LoxError>;
+    fn visit_call_expr(&mut self, call: &CallExpr) -> Result<T, LoxError>;
+    fn visit_get_expr(&mut self, get: &GetExpr) -> Result<T, LoxError>;
     fn visit_grouping_expr(&mut self, grouping: &GroupingExpr) -> Result<T, LoxError>;
     fn visit_variable_expr(&mut self, variable: &VariableExpr) -> Result<T, LoxError>;
 }
@@ -75,9 +166,27 @@ pub trait ExprVisitor<T> {
 
 #[derive(Debug)]
 pub enum Stmt {
+    Block(BlockStmt),
+    Class(ClassStmt),
     Expression(ExpressionStmt),
+    Function(Rc<FunctionStmt>),
+    If(IfStmt),
     Print(PrintStmt),
+    Return(ReturnStmt),
     Var(VarStmt),
+    While(WhileStmt),
+}
+
+#[derive(Debug)]
+pub struct BlockStmt {
+    pub statements: Vec<Stmt>,
+}
+
+#[derive(Debug)]
+pub struct ClassStmt {
+    pub name: Identifier,
+    pub superclass: Option<VariableExpr>,
+    pub methods: Vec<Rc<FunctionStmt>>,
 }
 
 #[derive(Debug)]
@@ -85,31 +194,115 @@ pub struct ExpressionStmt {
     pub expression: Expr,
 }
 
+/// A function declaration.
+///
+/// Function objects keep a reference to their declaration, so it lives
+/// behind an `Rc` in the AST.
+#[derive(Debug)]
+pub struct FunctionStmt {
+    pub name: Identifier,
+    pub params: Vec<Identifier>,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Debug)]
+pub struct IfStmt {
+    pub keyword: Token,
+    pub condition: Expr,
+    pub then_branch: Box<Stmt>,
+    pub else_branch: Option<Box<Stmt>>,
+}
+
 #[derive(Debug)]
 pub struct PrintStmt {
     pub expression: Expr,
 }
 
+#[derive(Debug)]
+pub struct ReturnStmt {
+    pub keyword: Token,
+    pub value: Option<Expr>,
+}
+
 #[derive(Debug)]
 pub struct VarStmt {
-    pub name: Token,
+    pub name: Identifier,
     pub initializer: Option<Expr>,
 }
 
+#[derive(Debug)]
+pub struct WhileStmt {
+    pub keyword: Token,
+    pub condition: Expr,
+    pub body: Box<Stmt>,
+}
+
 impl Stmt {
     pub fn accept<T>(&self, stmt_visitor: &mut dyn StmtVisitor<T>) -> Result<T, LoxError> {
         match self {
+            Stmt::Block(v) => v.accept(stmt_visitor),
+            Stmt::Class(v) => v.accept(stmt_visitor),
             Stmt::Expression(v) => v.accept(stmt_visitor),
+            Stmt::Function(v) => stmt_visitor.visit_function(v),
+            Stmt::If(v) => v.accept(stmt_visitor),
             Stmt::Print(v) => v.accept(stmt_visitor),
+            Stmt::Return(v) => v.accept(stmt_visitor),
             Stmt::Var(v) => v.accept(stmt_visitor),
+            Stmt::While(v) => v.accept(stmt_visitor),
         }
     }
+
+    /// Roughly the span of source code this statement was parsed from. The
+    /// parser doesn't keep every token, so it can miss a keyword or the
+    /// closing `;`, and an empty block has no span at all.
+    pub fn span(&self) -> Option<Span> {
+        let span = match self {
+            Stmt::Block(v) => {
+                let first = v.statements.first()?.span()?;
+                let last = v.statements.last()?.span()?;
+                first.join(last)
+            }
+            Stmt::Class(v) => v.name.span,
+            Stmt::Expression(v) => v.expression.span(),
+            Stmt::Function(v) => v.name.span,
+            Stmt::If(v) => v.keyword.span.join(v.condition.span()),
+            Stmt::Print(v) => v.expression.span(),
+            Stmt::Return(v) => match &v.value {
+                Some(value) => v.keyword.span.join(value.span()),
+                None => v.keyword.span,
+            },
+            Stmt::Var(v) => match &v.initializer {
+                Some(initializer) => v.name.span.join(initializer.span()),
+                None => v.name.span,
+            },
+            Stmt::While(v) => v.keyword.span.join(v.condition.span()),
+        };
+        Some(span)
+    }
 }
 
 pub trait StmtVisitor<T> {
+    fn visit_block(&mut self, block: &BlockStmt) -> Result<T, LoxError>;
+    fn visit_class(&mut self, stmt: &ClassStmt) -> Result<T, LoxError>;
     fn visit_expr(&mut self, expr: &ExpressionStmt) -> Result<T, LoxError>;
+    fn visit_function(&mut self, stmt: &Rc<FunctionStmt>) -> Result<T, LoxError>;
+    fn visit_if(&mut self, stmt: &IfStmt) -> Result<T, LoxError>;
     fn visit_print(&mut self, expr: &PrintStmt) -> Result<T, LoxError>;
+    fn visit_return(&mut self, stmt: &ReturnStmt) -> Result<T, LoxError>;
     fn visit_var(&mut self, expr: &VarStmt) -> Result<T, LoxError>;
+    fn visit_while(&mut self, stmt: &WhileStmt) -> Result<T, LoxError>;
+}
+
+impl BlockStmt {
+    pub fn accept<T>(&self, visitor: &mut dyn StmtVisitor<T>) -> Result<T, LoxError> {
+        visitor.visit_block(self)
+    }
+}
+
+impl ClassStmt {
+    pub fn accept<T>(&self, visitor: &mut dyn StmtVisitor<T>) -> Result<T, LoxError> {
+        visitor.visit_class(self)
+    }
 }
 
 impl ExpressionStmt {
@@ -118,14 +311,32 @@ impl ExpressionStmt {
     }
 }
 
+impl IfStmt {
+    pub fn accept<T>(&self, visitor: &mut dyn StmtVisitor<T>) -> Result<T, LoxError> {
+        visitor.visit_if(self)
+    }
+}
+
 impl PrintStmt {
     pub fn accept<T>(&self, visitor: &mut dyn StmtVisitor<T>) -> Result<T, LoxError> {
         visitor.visit_print(self)
     }
 }
 
+impl ReturnStmt {
+    pub fn accept<T>(&self, visitor: &mut dyn StmtVisitor<T>) -> Result<T, LoxError> {
+        visitor.visit_return(self)
+    }
+}
+
 impl VarStmt {
     pub fn accept<T>(&self, visitor: &mut dyn StmtVisitor<T>) -> Result<T, LoxError> {
         visitor.visit_var(self)
     }
 }
+
+impl WhileStmt {
+    pub fn accept<T>(&self, visitor: &mut dyn StmtVisitor<T>) -> Result<T, LoxError> {
+        visitor.visit_while(self)
+    }
+}
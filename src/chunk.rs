@@ -0,0 +1,338 @@
+use crate::interner::{LoxString, Symbol};
+use crate::tokens::{Object, Span};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher, RandomState};
+use std::rc::Rc;
+
+/// The instructions of the bytecode VM. Operands follow the opcode byte;
+/// constant, name and jump operands are two bytes, big endian. Names are
+/// indices into the chunk's name table.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OpCode {
+    /// `constant: u16`. Pushes a constant.
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    /// `slot: u8`
+    GetLocal,
+    /// `slot: u8`
+    SetLocal,
+    /// `name: u16`
+    GetGlobal,
+    /// `name: u16`
+    DefineGlobal,
+    /// `name: u16`
+    SetGlobal,
+    /// `index: u8`
+    GetUpvalue,
+    /// `index: u8`
+    SetUpvalue,
+    /// `name: u16`
+    GetProperty,
+    /// `name: u16`
+    SetProperty,
+    /// `name: u16`. Pops the superclass and `this`, and pushes the bound method.
+    GetSuper,
+    Equal,
+    NotEqual,
+    /// `operator: u8`, one of [`BinaryOp`]. The opcode byte's span is the
+    /// left operand's and the operator byte's the right one's, so that type
+    /// errors can point at the operand that is wrong.
+    Binary,
+    Not,
+    Negate,
+    Print,
+    /// `offset: u16`
+    Jump,
+    /// `offset: u16`. Jumps if the value on top of the stack is `false`,
+    /// without popping it. Errors if it isn't a boolean.
+    JumpIfFalse,
+    /// `offset: u16`. Jumps backwards.
+    Loop,
+    /// `arguments: u8`. The opcode byte's span is the closing parenthesis and
+    /// the operand's the whole call.
+    Call,
+    /// `function: u16`, then an `is_local: u8` and `index: u8` pair for every
+    /// variable the function captures.
+    Closure,
+    CloseUpvalue,
+    Return,
+    /// `name: u16`
+    Class,
+    /// Copies the methods of the superclass below the class on the stack into
+    /// it, and pops the class.
+    Inherit,
+    /// `name: u16`. Pops a closure and adds it to the class below it.
+    Method,
+}
+
+impl OpCode {
+    const ALL: [OpCode; 31] = [
+        OpCode::Constant,
+        OpCode::Nil,
+        OpCode::True,
+        OpCode::False,
+        OpCode::Pop,
+        OpCode::GetLocal,
+        OpCode::SetLocal,
+        OpCode::GetGlobal,
+        OpCode::DefineGlobal,
+        OpCode::SetGlobal,
+        OpCode::GetUpvalue,
+        OpCode::SetUpvalue,
+        OpCode::GetProperty,
+        OpCode::SetProperty,
+        OpCode::GetSuper,
+        OpCode::Equal,
+        OpCode::NotEqual,
+        OpCode::Binary,
+        OpCode::Not,
+        OpCode::Negate,
+        OpCode::Print,
+        OpCode::Jump,
+        OpCode::JumpIfFalse,
+        OpCode::Loop,
+        OpCode::Call,
+        OpCode::Closure,
+        OpCode::CloseUpvalue,
+        OpCode::Return,
+        OpCode::Class,
+        OpCode::Inherit,
+        OpCode::Method,
+    ];
+}
+
+/// Decodes an opcode byte, giving the byte back if it isn't one.
+impl TryFrom<u8> for OpCode {
+    type Error = u8;
+
+    fn try_from(byte: u8) -> Result<Self, u8> {
+        Self::ALL.get(byte as usize).copied().ok_or(byte)
+    }
+}
+
+/// The arithmetic and comparison operators of [`OpCode::Binary`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+}
+
+impl BinaryOp {
+    const ALL: [BinaryOp; 8] = [
+        BinaryOp::Add,
+        BinaryOp::Subtract,
+        BinaryOp::Multiply,
+        BinaryOp::Divide,
+        BinaryOp::Greater,
+        BinaryOp::GreaterEqual,
+        BinaryOp::Less,
+        BinaryOp::LessEqual,
+    ];
+}
+
+impl TryFrom<u8> for BinaryOp {
+    type Error = u8;
+
+    fn try_from(byte: u8) -> Result<Self, u8> {
+        Self::ALL.get(byte as usize).copied().ok_or(byte)
+    }
+}
+
+/// Constants that are the same value can share a slot in the pool.
+#[derive(PartialEq, Eq, Hash)]
+enum ConstantKey {
+    Num(u64),
+    Str(LoxString),
+}
+
+/// An entry in a chunk's line table: the bytes from `offset` up to the next
+/// entry were compiled from the source between `start` and `end`. Sources
+/// over 4GiB would take far more memory than that to parse, so 32 bits is
+/// plenty.
+struct Line {
+    offset: u32,
+    start: u32,
+    end: u32,
+}
+
+/// Hashes the constant and name table keys, which are single integers. The
+/// default SipHash took longer than anything else in compiling long,
+/// straight-line programs, so this uses a folded multiply instead. Like
+/// SipHash it's seeded randomly, so that a script can't be written to make
+/// its constants all collide.
+#[derive(Clone)]
+struct FoldHashState {
+    seed: u64,
+}
+
+impl Default for FoldHashState {
+    fn default() -> Self {
+        Self {
+            seed: RandomState::new().hash_one(0),
+        }
+    }
+}
+
+impl BuildHasher for FoldHashState {
+    type Hasher = FoldHasher;
+
+    fn build_hasher(&self) -> FoldHasher {
+        FoldHasher { hash: self.seed }
+    }
+}
+
+struct FoldHasher {
+    hash: u64,
+}
+
+impl Hasher for FoldHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.write_u64(u64::from(*byte));
+        }
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        // the high half of the product mixes the high bits of `n` into the
+        // low bits of the hash, which the table indexes buckets with
+        let product = u128::from(self.hash ^ n) * 0x9e37_79b9_7f4a_7c15;
+        self.hash = (product as u64) ^ ((product >> 64) as u64);
+    }
+
+    fn write_u32(&mut self, n: u32) {
+        self.write_u64(u64::from(n));
+    }
+
+    fn write_usize(&mut self, n: usize) {
+        self.write_u64(n as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+/// A compiled piece of code.
+#[derive(Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Object>,
+    /// The names of the variables, properties, methods and classes that
+    /// instructions refer to.
+    pub names: Vec<Symbol>,
+    /// The functions declared in this code, which `Closure` instructions
+    /// turn into closures.
+    pub functions: Vec<Rc<Function>>,
+    /// The line table, with an entry for each run of bytes compiled from the
+    /// same span of source.
+    lines: Vec<Line>,
+    /// The entry of the line table that was looked up last.
+    last_line: Cell<usize>,
+    constant_indices: HashMap<ConstantKey, usize, FoldHashState>,
+    name_indices: HashMap<Symbol, usize, FoldHashState>,
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends an instruction, or part of one, compiled from `span`.
+    pub fn write(&mut self, bytes: &[u8], span: Span) {
+        let (start, end) = (span.start as u32, span.end as u32);
+        if self
+            .lines
+            .last()
+            .is_none_or(|last| (last.start, last.end) != (start, end))
+        {
+            self.lines.push(Line {
+                offset: self.code.len() as u32,
+                start,
+                end,
+            });
+        }
+        self.code.extend_from_slice(bytes);
+    }
+
+    /// Adds a constant to the pool, returning its index. Numbers and strings
+    /// that are already in the pool are reused.
+    pub fn add_constant(&mut self, value: Object) -> usize {
+        let key = match &value {
+            Object::Num(n) => Some(ConstantKey::Num(n.to_bits())),
+            Object::Str(s) => Some(ConstantKey::Str(s.clone())),
+            _ => None,
+        };
+
+        if let Some(index) = key.as_ref().and_then(|key| self.constant_indices.get(key)) {
+            return *index;
+        }
+
+        self.constants.push(value);
+        let index = self.constants.len() - 1;
+        if let Some(key) = key {
+            self.constant_indices.insert(key, index);
+        }
+        index
+    }
+
+    /// Adds a name to the name table, returning its index.
+    pub fn add_name(&mut self, name: Symbol) -> usize {
+        let names = &mut self.names;
+        *self.name_indices.entry(name).or_insert_with(|| {
+            names.push(name);
+            names.len() - 1
+        })
+    }
+
+    /// The span of source code the byte at `offset` was compiled from. This
+    /// is cheap enough to look up for every instruction that might fail:
+    /// code mostly runs in order, so the entry is usually the one looked up
+    /// last or one of the next few, and otherwise it's found by a binary
+    /// search.
+    pub fn span_at(&self, offset: usize) -> Span {
+        let lines = &self.lines;
+        let contains = |index: usize| {
+            lines[index].offset as usize <= offset
+                && lines
+                    .get(index + 1)
+                    .is_none_or(|next| offset < next.offset as usize)
+        };
+
+        let last = self.last_line.get();
+        let index = (last..lines.len().min(last + 4))
+            .find(|&index| contains(index))
+            .unwrap_or_else(|| lines.partition_point(|line| line.offset as usize <= offset) - 1);
+        self.last_line.set(index);
+
+        let line = &lines[index];
+        Span::from(line.start as usize..line.end as usize)
+    }
+}
+
+/// A function compiled to bytecode, before it has captured any variables.
+pub struct Function {
+    pub name: Symbol,
+    pub arity: usize,
+    /// How many variables of enclosing functions it captures.
+    pub upvalue_count: usize,
+    pub chunk: Chunk,
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<fn {}>", self.name)
+    }
+}
@@ -0,0 +1,159 @@
+use crate::error::{ErrorType, LoxError};
+use crate::function::{Arity, Callable, LoxFunction};
+use crate::gc::{address, Heap, Trace};
+use crate::interner::Symbol;
+use crate::interpreter::Interpreter;
+use crate::tokens::{Object, Span};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+pub struct LoxClass {
+    pub name: Symbol,
+    superclass: Option<Rc<LoxClass>>,
+    methods: HashMap<Symbol, Rc<LoxFunction>>,
+}
+
+impl LoxClass {
+    pub fn new(
+        name: Symbol,
+        superclass: Option<Rc<LoxClass>>,
+        methods: HashMap<Symbol, Rc<LoxFunction>>,
+    ) -> Self {
+        Self {
+            name,
+            superclass,
+            methods,
+        }
+    }
+
+    /// Looks up a method on this class, falling back to the superclass chain.
+    pub fn find_method(&self, name: Symbol) -> Option<Rc<LoxFunction>> {
+        match self.methods.get(&name) {
+            Some(method) => Some(Rc::clone(method)),
+            None => self
+                .superclass
+                .as_ref()
+                .and_then(|superclass| superclass.find_method(name)),
+        }
+    }
+}
+
+// implemented on the `Rc` so that new instances can point back at their class
+impl Callable for Rc<LoxClass> {
+    fn arity(&self) -> Arity {
+        self.find_method(Symbol::INIT)
+            .map_or(Arity::Exactly(0), |initializer| initializer.arity())
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Object>,
+    ) -> Result<Object, LoxError> {
+        interpreter.allocate(std::mem::size_of::<LoxInstance>(), interpreter.call_span())?;
+        let instance = interpreter
+            .heap()
+            .manage(RefCell::new(LoxInstance::new(Rc::clone(self))));
+
+        if let Some(initializer) = self.find_method(Symbol::INIT) {
+            initializer
+                .bind(Rc::clone(&instance), interpreter.heap())
+                .call(interpreter, arguments)?;
+        }
+
+        Ok(Object::Instance(instance))
+    }
+}
+
+impl Trace for LoxClass {
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool {
+        if let Some(superclass) = &self.superclass {
+            visit(address(superclass));
+        }
+        for method in self.methods.values() {
+            visit(address(method));
+        }
+        true
+    }
+
+    // classes never change, the environments their methods close over are
+    // what gets cleared
+    fn clear(&self) {}
+}
+
+impl fmt::Debug for LoxClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<class {}>", self.name)
+    }
+}
+
+/// An instance of a class. Instances are shared by reference, so they are
+/// always handled through an `Rc<RefCell<LoxInstance>>`.
+pub struct LoxInstance {
+    pub class: Rc<LoxClass>,
+    fields: HashMap<Symbol, Object>,
+}
+
+impl LoxInstance {
+    pub fn new(class: Rc<LoxClass>) -> Self {
+        Self {
+            class,
+            fields: HashMap::new(),
+        }
+    }
+
+    /// Looks up a property, preferring fields over methods. Methods are bound
+    /// to `instance` so that `this` works once they are called.
+    pub fn get(
+        instance: &Rc<RefCell<Self>>,
+        name: Symbol,
+        span: Span,
+        heap: &mut Heap,
+    ) -> Result<Object, LoxError> {
+        if let Some(value) = instance.borrow().fields.get(&name) {
+            return Ok(value.clone());
+        }
+
+        let method = instance.borrow().class.find_method(name);
+        match method {
+            Some(method) => Ok(Object::Function(method.bind(Rc::clone(instance), heap))),
+            None => Err(LoxError::error(
+                span,
+                &format!("Undefined property '{}'.", name),
+                ErrorType::RuntimeError,
+            )),
+        }
+    }
+
+    pub fn set(&mut self, name: Symbol, value: Object) {
+        self.fields.insert(name, value);
+    }
+}
+
+impl Trace for RefCell<LoxInstance> {
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool {
+        let Ok(instance) = self.try_borrow() else {
+            return false;
+        };
+        visit(address(&instance.class));
+        for value in instance.fields.values() {
+            value.trace(visit);
+        }
+        true
+    }
+
+    fn clear(&self) {
+        if let Ok(mut instance) = self.try_borrow_mut() {
+            instance.fields.clear();
+        }
+    }
+}
+
+// fields can refer back to the instance, so don't recurse into them
+impl fmt::Debug for LoxInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<{} instance>", self.class.name)
+    }
+}
@@ -0,0 +1,185 @@
+use rlox::{Backend, Permission, Permissions, TraceFormat, Tracer};
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: rlox [options] [script]
+       rlox [options] run <script>
+       rlox [options] compile <script> [-o <output>]
+
+Runs `script`, or starts an interactive prompt if no script is given. Scripts
+can be Lox source code or programs compiled to .loxc files.
+
+`compile` parses `script` and saves it as a .loxc file, which can be run
+without parsing it again. It is saved next to `script` unless `-o` is given.
+
+Options:
+    --dump-tokens, --tokens  print the scanned tokens to stderr
+    --dump-ast, --ast        print the parsed syntax tree to stderr
+    --trace[=text|json]      log every statement, expression and variable
+                             definition to stderr (tree backend only)
+    --trace-lines=FROM-TO    only trace what happens on lines FROM to TO
+    --verbose                all of the above, except tracing with the vm backend
+    --gc-stats               print what the garbage collector did to stderr
+    --gc-stress              collect garbage on every allocation (slow)
+    --quiet                  don't print diagnostics, only exit with an error code
+    --backend=tree|vm        run code by walking the syntax tree (the default) or
+                             by compiling it to bytecode for a virtual machine
+    --allow-read[=DIR]       let scripts read files, or only those inside DIR
+    --allow-write[=DIR]      let scripts write files, or only those inside DIR
+    --allow-env              let scripts read environment variables
+    --allow-process          let scripts run other programs
+    --allow-all              all of the above, without restrictions
+    -o, --output <file>      where `compile` saves the compiled program
+    -h, --help               show this message";
+
+/// What to do with the script.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Command {
+    /// Run it, or start a prompt if there is no script.
+    #[default]
+    Run,
+    /// Save it as a `.loxc` file.
+    Compile,
+}
+
+/// Command line options.
+#[derive(Debug, Default)]
+pub struct Options {
+    pub command: Command,
+    pub script: Option<String>,
+    /// Where to save the program for [`Command::Compile`].
+    pub output: Option<String>,
+    pub dump_tokens: bool,
+    pub dump_ast: bool,
+    /// Trace execution in this format, if given.
+    pub trace: Option<TraceFormat>,
+    pub trace_lines: Option<RangeInclusive<usize>>,
+    pub quiet: bool,
+    pub gc_stats: bool,
+    pub gc_stress: bool,
+    pub help: bool,
+    /// What scripts are allowed to do. Nothing, unless `--allow-*` is given.
+    pub permissions: Permissions,
+    pub backend: Backend,
+}
+
+impl Options {
+    /// Parses the arguments following the program name.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+        let mut command = None;
+        let mut verbose = false;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--dump-tokens" | "--tokens" => options.dump_tokens = true,
+                "--dump-ast" | "--ast" => options.dump_ast = true,
+                "--trace" | "--trace=text" => options.trace = Some(TraceFormat::Text),
+                "--trace=json" => options.trace = Some(TraceFormat::Json),
+                "--verbose" => {
+                    options.dump_tokens = true;
+                    options.dump_ast = true;
+                    verbose = true;
+                }
+                "--quiet" => options.quiet = true,
+                "--gc-stats" => options.gc_stats = true,
+                "--gc-stress" => options.gc_stress = true,
+                "-h" | "--help" => options.help = true,
+                "-o" | "--output" => match args.next() {
+                    Some(output) => options.output = Some(output),
+                    None => return Err(format!("'{arg}' needs a file name")),
+                },
+                "--allow-read" => options.permissions.grant(Permission::Read(None)),
+                "--allow-write" => options.permissions.grant(Permission::Write(None)),
+                "--allow-env" => options.permissions.grant(Permission::Env),
+                "--allow-process" => options.permissions.grant(Permission::Process),
+                "--allow-all" => options.permissions = Permissions::all(),
+                "--backend=tree" => options.backend = Backend::Tree,
+                "--backend=vm" => options.backend = Backend::Vm,
+                flag if flag.starts_with("--backend=") => {
+                    return Err(format!(
+                        "unknown backend in '{flag}', expected 'tree' or 'vm'"
+                    ))
+                }
+                flag if flag.starts_with("--trace=") => {
+                    return Err(format!(
+                        "unknown trace format in '{flag}', expected 'text' or 'json'"
+                    ))
+                }
+                flag if flag.starts_with("--trace-lines=") => {
+                    options.trace_lines = Some(parse_lines(&flag["--trace-lines=".len()..])?);
+                }
+                flag if flag.starts_with("--allow-read=") => {
+                    let dir = PathBuf::from(&flag["--allow-read=".len()..]);
+                    options.permissions.grant(Permission::Read(Some(dir)));
+                }
+                flag if flag.starts_with("--allow-write=") => {
+                    let dir = PathBuf::from(&flag["--allow-write=".len()..]);
+                    options.permissions.grant(Permission::Write(Some(dir)));
+                }
+                flag if flag.starts_with('-') => return Err(format!("unknown option '{flag}'")),
+                "run" if command.is_none() && options.script.is_none() => {
+                    command = Some(Command::Run)
+                }
+                "compile" if command.is_none() && options.script.is_none() => {
+                    command = Some(Command::Compile)
+                }
+                _ if options.script.is_some() => {
+                    return Err("only one script can be run at a time".to_string())
+                }
+                _ => options.script = Some(arg),
+            }
+        }
+
+        options.command = command.unwrap_or_default();
+        match options.command {
+            Command::Run if command.is_some() && options.script.is_none() => {
+                return Err("'run' needs a script".to_string())
+            }
+            Command::Compile if options.script.is_none() => {
+                return Err("'compile' needs a script".to_string())
+            }
+            Command::Run if options.output.is_some() => {
+                return Err("'-o' can only be used with 'compile'".to_string())
+            }
+            _ => {}
+        }
+
+        // the VM has no tracer hooks, so it would silently log nothing
+        if options.trace.is_some() && options.backend == Backend::Vm {
+            return Err("tracing only works with '--backend=tree'".to_string());
+        }
+        // which is fine for `--verbose`, which just shows what it can
+        if verbose && options.trace.is_none() && options.backend == Backend::Tree {
+            options.trace = Some(TraceFormat::Text);
+        }
+
+        Ok(options)
+    }
+
+    /// The tracer asked for, if any.
+    pub fn tracer(&self) -> Option<Tracer> {
+        let tracer = Tracer::new(self.trace?);
+        match &self.trace_lines {
+            Some(lines) => Some(tracer.lines(lines.clone())),
+            None => Some(tracer),
+        }
+    }
+}
+
+/// Parses a line range like `10-20`, `10-` or `10`.
+fn parse_lines(range: &str) -> Result<RangeInclusive<usize>, String> {
+    let invalid = || format!("invalid line range '{range}', expected FROM-TO");
+    let number = |text: &str| text.parse::<usize>().map_err(|_| invalid());
+
+    match range.split_once('-') {
+        Some((from, "")) => Ok(number(from)?..=usize::MAX),
+        Some((from, to)) => Ok(number(from)?..=number(to)?),
+        None => {
+            let line = number(range)?;
+            Ok(line..=line)
+        }
+    }
+}
@@ -0,0 +1,668 @@
+use crate::ast::*;
+use crate::chunk::{BinaryOp, Chunk, Function, OpCode};
+use crate::error::{ErrorType, LoxError};
+use crate::interner::Symbol;
+use crate::tokens::{KeywordType, Object, Span, TokenType};
+use std::rc::Rc;
+
+/// Compiles a program for the bytecode VM. The result is a function that
+/// takes no arguments and runs the program when called.
+pub fn compile(source: &str, statements: &[Stmt]) -> Result<Function, LoxError> {
+    let mut compiler = Compiler::new();
+    for statement in statements {
+        statement.accept(&mut compiler)?;
+    }
+    Ok(compiler.finish(Span::from(source.len()..source.len())))
+}
+
+/// Compiles a single expression into a function that returns its value.
+pub fn compile_expression(expr: &Expr) -> Result<Function, LoxError> {
+    let mut compiler = Compiler::new();
+    expr.accept(&mut compiler)?;
+    compiler.emit_op(OpCode::Return, expr.span());
+    Ok(compiler.functions.pop().unwrap().function)
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum FunctionType {
+    Script,
+    Function,
+    Method,
+    Initializer,
+}
+
+/// A variable that lives in a slot on the VM's stack.
+struct Local {
+    /// `None` for the function being called, which can't be referred to by
+    /// name.
+    name: Option<Symbol>,
+    depth: usize,
+    /// Whether a closure refers to it, in which case it has to be moved off
+    /// the stack when it goes out of scope.
+    captured: bool,
+}
+
+/// A variable of an enclosing function that a closure refers to.
+#[derive(Copy, Clone, PartialEq, Eq)]
+struct Upvalue {
+    /// Whether it is a local of the function directly around this one,
+    /// rather than one of that function's own upvalues.
+    is_local: bool,
+    index: u8,
+}
+
+/// The state of one function being compiled.
+struct FunctionState {
+    function: Function,
+    function_type: FunctionType,
+    locals: Vec<Local>,
+    upvalues: Vec<Upvalue>,
+    scope_depth: usize,
+}
+
+impl FunctionState {
+    fn new(name: Symbol, function_type: FunctionType) -> Self {
+        // slot zero holds the function being called, or `this` in methods
+        let slot_zero = match function_type {
+            FunctionType::Method | FunctionType::Initializer => Some(Symbol::THIS),
+            FunctionType::Script | FunctionType::Function => None,
+        };
+
+        Self {
+            function: Function {
+                name,
+                arity: 0,
+                upvalue_count: 0,
+                chunk: Chunk::new(),
+            },
+            function_type,
+            locals: vec![Local {
+                name: slot_zero,
+                depth: 0,
+                captured: false,
+            }],
+            upvalues: vec![],
+            scope_depth: 0,
+        }
+    }
+}
+
+/// Walks the syntax tree emitting bytecode. Variables are resolved while
+/// compiling: locals and captured variables get stack slots and upvalue
+/// indices, and anything that isn't declared in an enclosing scope is looked
+/// up by name among the globals at runtime.
+struct Compiler {
+    /// The functions being compiled, innermost last.
+    functions: Vec<FunctionState>,
+}
+
+impl Compiler {
+    fn new() -> Self {
+        Self {
+            functions: vec![FunctionState::new(
+                Symbol::intern("script"),
+                FunctionType::Script,
+            )],
+        }
+    }
+
+    fn current(&mut self) -> &mut FunctionState {
+        self.functions.last_mut().unwrap()
+    }
+
+    fn chunk(&mut self) -> &mut Chunk {
+        &mut self.current().function.chunk
+    }
+
+    fn emit(&mut self, byte: u8, span: Span) {
+        self.chunk().write(&[byte], span);
+    }
+
+    fn emit_op(&mut self, op: OpCode, span: Span) {
+        self.emit(op as u8, span);
+    }
+
+    /// Emits an instruction with a two byte operand.
+    fn emit_with_u16(&mut self, op: OpCode, value: u16, span: Span) {
+        let [high, low] = value.to_be_bytes();
+        self.chunk().write(&[op as u8, high, low], span);
+    }
+
+    fn emit_constant(&mut self, value: Object, span: Span) -> Result<(), LoxError> {
+        let constant = self.make_constant(value, span)?;
+        self.emit_with_u16(OpCode::Constant, constant, span);
+        Ok(())
+    }
+
+    fn make_constant(&mut self, value: Object, span: Span) -> Result<u16, LoxError> {
+        let index = self.chunk().add_constant(value);
+        u16::try_from(index).map_err(|_| error(span, "Too many constants in one chunk."))
+    }
+
+    fn make_name(&mut self, name: Symbol, span: Span) -> Result<u16, LoxError> {
+        let index = self.chunk().add_name(name);
+        u16::try_from(index).map_err(|_| error(span, "Too many names in one chunk."))
+    }
+
+    /// Emits an instruction that takes `name` as its operand.
+    fn emit_named(&mut self, op: OpCode, name: Identifier) -> Result<(), LoxError> {
+        let index = self.make_name(name.symbol, name.span)?;
+        self.emit_with_u16(op, index, name.span);
+        Ok(())
+    }
+
+    /// Emits a jump with a placeholder offset, returning where the offset is
+    /// so that it can be patched once the target is known.
+    fn emit_jump(&mut self, op: OpCode, span: Span) -> usize {
+        self.emit_with_u16(op, u16::MAX, span);
+        self.chunk().code.len() - 2
+    }
+
+    fn patch_jump(&mut self, offset: usize, span: Span) -> Result<(), LoxError> {
+        // jump over the offset itself too
+        let jump = self.chunk().code.len() - offset - 2;
+        let jump = u16::try_from(jump).map_err(|_| error(span, "Too much code to jump over."))?;
+        self.chunk().code[offset..offset + 2].copy_from_slice(&jump.to_be_bytes());
+        Ok(())
+    }
+
+    fn emit_loop(&mut self, loop_start: usize, span: Span) -> Result<(), LoxError> {
+        // the jump is from the end of the instruction, so it includes itself
+        let offset = self.chunk().code.len() + 3 - loop_start;
+        let offset = u16::try_from(offset).map_err(|_| error(span, "Loop body too large."))?;
+        self.emit_with_u16(OpCode::Loop, offset, span);
+        Ok(())
+    }
+
+    /// Emits the implicit return at the end of a function.
+    fn emit_return(&mut self, span: Span) {
+        if self.current().function_type == FunctionType::Initializer {
+            // initializers always return `this`
+            self.emit_op(OpCode::GetLocal, span);
+            self.emit(0, span);
+        } else {
+            self.emit_op(OpCode::Nil, span);
+        }
+        self.emit_op(OpCode::Return, span);
+    }
+
+    fn finish(mut self, span: Span) -> Function {
+        self.emit_return(span);
+        self.functions.pop().unwrap().function
+    }
+
+    fn begin_scope(&mut self) {
+        self.current().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self, span: Span) {
+        let state = self.current();
+        state.scope_depth -= 1;
+
+        let mut ops = vec![];
+        while let Some(local) = state.locals.last() {
+            if local.depth <= state.scope_depth {
+                break;
+            }
+            ops.push(if local.captured {
+                OpCode::CloseUpvalue
+            } else {
+                OpCode::Pop
+            });
+            state.locals.pop();
+        }
+
+        for op in ops {
+            self.emit_op(op, span);
+        }
+    }
+
+    /// Declares a local variable whose value is on top of the stack. Like
+    /// globals, locals can be redeclared in the same scope, in which case the
+    /// existing slot is reused.
+    fn declare_local(&mut self, name: Identifier) -> Result<(), LoxError> {
+        let state = self.current();
+
+        let existing = state
+            .locals
+            .iter()
+            .rposition(|local| local.depth == state.scope_depth && local.name == Some(name.symbol));
+        if let Some(slot) = existing {
+            self.emit_op(OpCode::SetLocal, name.span);
+            self.emit(slot as u8, name.span);
+            self.emit_op(OpCode::Pop, name.span);
+            return Ok(());
+        }
+
+        if state.locals.len() > u8::MAX as usize {
+            return Err(error(name.span, "Too many local variables in function."));
+        }
+        let depth = state.scope_depth;
+        state.locals.push(Local {
+            name: Some(name.symbol),
+            depth,
+            captured: false,
+        });
+        Ok(())
+    }
+
+    /// Defines a variable whose value is on top of the stack, as a global at
+    /// the top level and as a local anywhere else.
+    fn define_variable(&mut self, name: Identifier) -> Result<(), LoxError> {
+        if self.current().scope_depth == 0 {
+            self.emit_named(OpCode::DefineGlobal, name)
+        } else {
+            self.declare_local(name)
+        }
+    }
+
+    fn resolve_local(&self, function: usize, name: Symbol) -> Option<u8> {
+        self.functions[function]
+            .locals
+            .iter()
+            .rposition(|local| local.name == Some(name))
+            .map(|slot| slot as u8)
+    }
+
+    fn resolve_upvalue(
+        &mut self,
+        function: usize,
+        name: Symbol,
+        span: Span,
+    ) -> Result<Option<u8>, LoxError> {
+        if function == 0 {
+            return Ok(None);
+        }
+
+        if let Some(slot) = self.resolve_local(function - 1, name) {
+            self.functions[function - 1].locals[slot as usize].captured = true;
+            return self
+                .add_upvalue(
+                    function,
+                    Upvalue {
+                        is_local: true,
+                        index: slot,
+                    },
+                    span,
+                )
+                .map(Some);
+        }
+
+        match self.resolve_upvalue(function - 1, name, span)? {
+            Some(index) => self
+                .add_upvalue(
+                    function,
+                    Upvalue {
+                        is_local: false,
+                        index,
+                    },
+                    span,
+                )
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn add_upvalue(
+        &mut self,
+        function: usize,
+        upvalue: Upvalue,
+        span: Span,
+    ) -> Result<u8, LoxError> {
+        let state = &mut self.functions[function];
+        if let Some(index) = state
+            .upvalues
+            .iter()
+            .position(|existing| *existing == upvalue)
+        {
+            return Ok(index as u8);
+        }
+
+        if state.upvalues.len() > u8::MAX as usize {
+            return Err(error(span, "Too many closure variables in function."));
+        }
+        state.upvalues.push(upvalue);
+        state.function.upvalue_count = state.upvalues.len();
+        Ok((state.upvalues.len() - 1) as u8)
+    }
+
+    /// Emits the instruction that reads or writes the variable `name`,
+    /// wherever it turns out to live.
+    fn variable(&mut self, name: Symbol, span: Span, assign: bool) -> Result<(), LoxError> {
+        let current = self.functions.len() - 1;
+
+        let (op, operand) = if let Some(slot) = self.resolve_local(current, name) {
+            let op = if assign {
+                OpCode::SetLocal
+            } else {
+                OpCode::GetLocal
+            };
+            (op, slot)
+        } else if let Some(index) = self.resolve_upvalue(current, name, span)? {
+            let op = if assign {
+                OpCode::SetUpvalue
+            } else {
+                OpCode::GetUpvalue
+            };
+            (op, index)
+        } else {
+            let op = if assign {
+                OpCode::SetGlobal
+            } else {
+                OpCode::GetGlobal
+            };
+            let index = self.make_name(name, span)?;
+            self.emit_with_u16(op, index, span);
+            return Ok(());
+        };
+
+        self.emit_op(op, span);
+        self.emit(operand, span);
+        Ok(())
+    }
+
+    /// Compiles a function declaration and emits the instruction that
+    /// creates a closure of it.
+    fn function(
+        &mut self,
+        stmt: &FunctionStmt,
+        function_type: FunctionType,
+    ) -> Result<(), LoxError> {
+        let mut state = FunctionState::new(stmt.name.symbol, function_type);
+        state.function.arity = stmt.params.len();
+        state.scope_depth = 1;
+        self.functions.push(state);
+
+        for param in &stmt.params {
+            self.current().locals.push(Local {
+                name: Some(param.symbol),
+                depth: 1,
+                captured: false,
+            });
+        }
+        for statement in &stmt.body {
+            statement.accept(self)?;
+        }
+        self.emit_return(stmt.name.span);
+
+        let state = self.functions.pop().unwrap();
+        let span = stmt.name.span;
+        let chunk = self.chunk();
+        chunk.functions.push(Rc::new(state.function));
+        let index = u16::try_from(chunk.functions.len() - 1)
+            .map_err(|_| error(span, "Too many functions in one chunk."))?;
+
+        self.emit_with_u16(OpCode::Closure, index, span);
+        for upvalue in state.upvalues {
+            self.emit(upvalue.is_local as u8, span);
+            self.emit(upvalue.index, span);
+        }
+        Ok(())
+    }
+}
+
+impl ExprVisitor<()> for Compiler {
+    fn visit_assign_expr(&mut self, assign: &AssignExpr) -> Result<(), LoxError> {
+        assign.value.accept(self)?;
+        self.variable(assign.name.symbol, assign.name.span, true)
+    }
+
+    fn visit_literal_expr(&mut self, literal: &LiteralExpr) -> Result<(), LoxError> {
+        match &literal.value {
+            Object::Nil => self.emit_op(OpCode::Nil, literal.span),
+            Object::Bool(true) => self.emit_op(OpCode::True, literal.span),
+            Object::Bool(false) => self.emit_op(OpCode::False, literal.span),
+            value => self.emit_constant(value.clone(), literal.span)?,
+        }
+        Ok(())
+    }
+
+    fn visit_logical_expr(&mut self, logical: &LogicalExpr) -> Result<(), LoxError> {
+        logical.left.accept(self)?;
+        let left_span = logical.left.span();
+
+        // the left operand is left on the stack as the result if it decides it
+        match logical.operator.token_type {
+            TokenType::Keyword(KeywordType::And) => {
+                let end = self.emit_jump(OpCode::JumpIfFalse, left_span);
+                self.emit_op(OpCode::Pop, left_span);
+                logical.right.accept(self)?;
+                self.patch_jump(end, left_span)
+            }
+            _ => {
+                let else_jump = self.emit_jump(OpCode::JumpIfFalse, left_span);
+                let end = self.emit_jump(OpCode::Jump, left_span);
+                self.patch_jump(else_jump, left_span)?;
+                self.emit_op(OpCode::Pop, left_span);
+                logical.right.accept(self)?;
+                self.patch_jump(end, left_span)
+            }
+        }
+    }
+
+    fn visit_set_expr(&mut self, set: &SetExpr) -> Result<(), LoxError> {
+        set.object.accept(self)?;
+        set.value.accept(self)?;
+        self.emit_named(OpCode::SetProperty, set.name)
+    }
+
+    fn visit_super_expr(&mut self, sup: &SuperExpr) -> Result<(), LoxError> {
+        self.variable(Symbol::THIS, sup.keyword.span, false)?;
+        self.variable(Symbol::SUPER, sup.keyword.span, false)?;
+        self.emit_named(OpCode::GetSuper, sup.method)
+    }
+
+    fn visit_this_expr(&mut self, this: &ThisExpr) -> Result<(), LoxError> {
+        self.variable(Symbol::THIS, this.keyword.span, false)
+    }
+
+    fn visit_unary_expr(&mut self, unary: &UnaryExpr) -> Result<(), LoxError> {
+        unary.right.accept(self)?;
+
+        let op = match unary.operator.token_type {
+            TokenType::Minus => OpCode::Negate,
+            _ => OpCode::Not,
+        };
+        // errors point at the operand, like they do in the tree walker
+        self.emit_op(op, unary.right.span());
+        Ok(())
+    }
+
+    fn visit_binary_expr(&mut self, binary: &BinaryExpr) -> Result<(), LoxError> {
+        binary.left.accept(self)?;
+        binary.right.accept(self)?;
+
+        let op = match binary.operator.token_type {
+            TokenType::EqualEqual | TokenType::BangEqual => {
+                let op = if binary.operator.token_type == TokenType::EqualEqual {
+                    OpCode::Equal
+                } else {
+                    OpCode::NotEqual
+                };
+                self.emit_op(op, binary.operator.span);
+                return Ok(());
+            }
+            TokenType::Plus => BinaryOp::Add,
+            TokenType::Minus => BinaryOp::Subtract,
+            TokenType::Star => BinaryOp::Multiply,
+            TokenType::Slash => BinaryOp::Divide,
+            TokenType::Greater => BinaryOp::Greater,
+            TokenType::GreaterEqual => BinaryOp::GreaterEqual,
+            TokenType::Less => BinaryOp::Less,
+            TokenType::LessEqual => BinaryOp::LessEqual,
+            _ => panic!("Something has gone very wrong in the compiler..."),
+        };
+        self.emit_op(OpCode::Binary, binary.left.span());
+        self.emit(op as u8, binary.right.span());
+        Ok(())
+    }
+
+    fn visit_call_expr(&mut self, call: &CallExpr) -> Result<(), LoxError> {
+        call.callee.accept(self)?;
+        for argument in &call.arguments {
+            argument.accept(self)?;
+        }
+
+        self.emit_op(OpCode::Call, call.paren.span);
+        self.emit(
+            call.arguments.len() as u8,
+            call.callee.span().join(call.paren.span),
+        );
+        Ok(())
+    }
+
+    fn visit_get_expr(&mut self, get: &GetExpr) -> Result<(), LoxError> {
+        get.object.accept(self)?;
+        self.emit_named(OpCode::GetProperty, get.name)
+    }
+
+    fn visit_grouping_expr(&mut self, grouping: &GroupingExpr) -> Result<(), LoxError> {
+        grouping.expression.accept(self)
+    }
+
+    fn visit_variable_expr(&mut self, variable: &VariableExpr) -> Result<(), LoxError> {
+        self.variable(variable.name.symbol, variable.name.span, false)
+    }
+}
+
+impl StmtVisitor<()> for Compiler {
+    fn visit_block(&mut self, block: &BlockStmt) -> Result<(), LoxError> {
+        self.begin_scope();
+        for statement in &block.statements {
+            statement.accept(self)?;
+        }
+
+        // popping locals can't fail, so any span will do
+        let span = match self.chunk().code.len() {
+            0 => Span::from(0..0),
+            len => self.chunk().span_at(len - 1),
+        };
+        self.end_scope(span);
+        Ok(())
+    }
+
+    fn visit_class(&mut self, stmt: &ClassStmt) -> Result<(), LoxError> {
+        self.emit_named(OpCode::Class, stmt.name)?;
+        self.define_variable(stmt.name)?;
+
+        // methods of a subclass capture a variable holding the superclass, so
+        // `super.method()` always resolves relative to the class it appears in
+        if let Some(superclass) = &stmt.superclass {
+            self.visit_variable_expr(superclass)?;
+            self.begin_scope();
+            let state = self.current();
+            state.locals.push(Local {
+                name: Some(Symbol::SUPER),
+                depth: state.scope_depth,
+                captured: false,
+            });
+
+            self.variable(stmt.name.symbol, stmt.name.span, false)?;
+            self.emit_op(OpCode::Inherit, superclass.name.span);
+        }
+
+        self.variable(stmt.name.symbol, stmt.name.span, false)?;
+        for method in &stmt.methods {
+            let function_type = if method.name.symbol == Symbol::INIT {
+                FunctionType::Initializer
+            } else {
+                FunctionType::Method
+            };
+            self.function(method, function_type)?;
+            self.emit_named(OpCode::Method, method.name)?;
+        }
+        self.emit_op(OpCode::Pop, stmt.name.span);
+
+        if stmt.superclass.is_some() {
+            self.end_scope(stmt.name.span);
+        }
+        Ok(())
+    }
+
+    fn visit_expr(&mut self, stmt: &ExpressionStmt) -> Result<(), LoxError> {
+        stmt.expression.accept(self)?;
+        self.emit_op(OpCode::Pop, stmt.expression.span());
+        Ok(())
+    }
+
+    fn visit_function(&mut self, stmt: &Rc<FunctionStmt>) -> Result<(), LoxError> {
+        let name = Some(stmt.name.symbol);
+        let state = self.current();
+        let is_new_local = state.scope_depth > 0
+            && !state
+                .locals
+                .iter()
+                .any(|local| local.depth == state.scope_depth && local.name == name);
+
+        // a local function is declared before its body is compiled, so that
+        // it can call itself
+        if is_new_local {
+            self.declare_local(stmt.name)?;
+            return self.function(stmt, FunctionType::Function);
+        }
+        self.function(stmt, FunctionType::Function)?;
+        self.define_variable(stmt.name)
+    }
+
+    fn visit_if(&mut self, stmt: &IfStmt) -> Result<(), LoxError> {
+        let span = stmt.condition.span();
+        stmt.condition.accept(self)?;
+
+        let else_jump = self.emit_jump(OpCode::JumpIfFalse, span);
+        self.emit_op(OpCode::Pop, span);
+        stmt.then_branch.accept(self)?;
+        let end = self.emit_jump(OpCode::Jump, span);
+
+        self.patch_jump(else_jump, span)?;
+        self.emit_op(OpCode::Pop, span);
+        if let Some(else_branch) = &stmt.else_branch {
+            else_branch.accept(self)?;
+        }
+        self.patch_jump(end, span)
+    }
+
+    fn visit_print(&mut self, stmt: &PrintStmt) -> Result<(), LoxError> {
+        stmt.expression.accept(self)?;
+        self.emit_op(OpCode::Print, stmt.expression.span());
+        Ok(())
+    }
+
+    fn visit_return(&mut self, stmt: &ReturnStmt) -> Result<(), LoxError> {
+        match &stmt.value {
+            Some(value) => {
+                value.accept(self)?;
+                self.emit_op(OpCode::Return, stmt.keyword.span);
+            }
+            None => self.emit_return(stmt.keyword.span),
+        }
+        Ok(())
+    }
+
+    fn visit_var(&mut self, stmt: &VarStmt) -> Result<(), LoxError> {
+        // uninitialized variables start out as nil
+        match &stmt.initializer {
+            Some(initializer) => initializer.accept(self)?,
+            None => self.emit_op(OpCode::Nil, stmt.name.span),
+        }
+        self.define_variable(stmt.name)
+    }
+
+    fn visit_while(&mut self, stmt: &WhileStmt) -> Result<(), LoxError> {
+        let span = stmt.condition.span();
+        let loop_start = self.chunk().code.len();
+        stmt.condition.accept(self)?;
+
+        let exit = self.emit_jump(OpCode::JumpIfFalse, span);
+        self.emit_op(OpCode::Pop, span);
+        stmt.body.accept(self)?;
+        self.emit_loop(loop_start, stmt.keyword.span)?;
+
+        self.patch_jump(exit, span)?;
+        self.emit_op(OpCode::Pop, span);
+        Ok(())
+    }
+}
+
+fn error(span: Span, message: &str) -> LoxError {
+    LoxError::error(span, message, ErrorType::ParseError)
+}
@@ -1,35 +1,145 @@
 use crate::error::{ErrorType, LoxError};
-use crate::tokens::{Object, Token};
+use crate::gc::{address, Trace};
+use crate::interner::Symbol;
+use crate::tokens::{Object, Span};
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::rc::Rc;
 
-pub struct Environment<'source> {
-    values: HashMap<String, Object>,
-    source: &'source str,
+/// A single scope of variable bindings.
+///
+/// Scopes are chained through `enclosing`, so lookups that miss here walk
+/// outward until they reach the global scope (which has no enclosing scope).
+#[derive(Debug, Default)]
+pub struct Environment {
+    values: HashMap<Symbol, Object>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
 }
 
-impl<'source> Environment<'source> {
-    pub fn new(source: &'source str) -> Self {
+impl Environment {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new scope nested inside `enclosing`.
+    pub fn with_enclosing(enclosing: Rc<RefCell<Environment>>) -> Self {
         Self {
             values: HashMap::new(),
-            source,
+            enclosing: Some(enclosing),
         }
     }
 
-    pub fn define(&mut self, name: String, value: Option<Object>) {
+    /// Returns the variables declared directly in this scope, sorted by name.
+    pub fn bindings(&self) -> Vec<(String, Object)> {
+        let mut bindings: Vec<_> = self
+            .values
+            .iter()
+            .map(|(name, value)| (name.as_str().to_string(), value.clone()))
+            .collect();
+        bindings.sort_by(|a, b| a.0.cmp(&b.0));
+        bindings
+    }
+
+    pub fn define(&mut self, name: Symbol, value: Object) {
         // this makes it so variable statements can redefine variables
-        self.values.insert(name, value.unwrap());
+        self.values.insert(name, value);
+    }
+
+    /// Reassigns an existing variable in the nearest scope that declares it.
+    pub fn assign(&mut self, name: Symbol, value: Object, span: Span) -> Result<(), LoxError> {
+        if let Some(slot) = self.values.get_mut(&name) {
+            *slot = value;
+            return Ok(());
+        }
+
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().assign(name, value, span),
+            None => Err(LoxError::error(
+                span,
+                &format!("Undefined variable '{}'.", name),
+                ErrorType::RuntimeError,
+            )),
+        }
+    }
+
+    pub fn get(&self, name: Symbol, span: Span) -> Result<Object, LoxError> {
+        if let Some(value) = self.values.get(&name) {
+            return Ok(value.clone());
+        }
+
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow().get(name, span),
+            None => Err(LoxError::error(
+                span,
+                &format!("Undefined variable '{}'.", name),
+                ErrorType::RuntimeError,
+            )),
+        }
+    }
+
+    /// Like [`Environment::get`], but only looks in the scope `distance`
+    /// scopes out, where the resolver found the variable.
+    pub fn get_at(&self, distance: usize, name: Symbol, span: Span) -> Result<Object, LoxError> {
+        match (distance, &self.enclosing) {
+            (0, _) | (_, None) => self.values.get(&name).cloned().ok_or_else(|| {
+                LoxError::error(
+                    span,
+                    &format!("Undefined variable '{}'.", name),
+                    ErrorType::RuntimeError,
+                )
+            }),
+            (_, Some(enclosing)) => enclosing.borrow().get_at(distance - 1, name, span),
+        }
+    }
+
+    /// Like [`Environment::assign`], but only looks in the scope `distance`
+    /// scopes out, where the resolver found the variable.
+    pub fn assign_at(
+        &mut self,
+        distance: usize,
+        name: Symbol,
+        value: Object,
+        span: Span,
+    ) -> Result<(), LoxError> {
+        match (distance, &self.enclosing) {
+            (0, _) | (_, None) => match self.values.get_mut(&name) {
+                Some(slot) => {
+                    *slot = value;
+                    Ok(())
+                }
+                None => Err(LoxError::error(
+                    span,
+                    &format!("Undefined variable '{}'.", name),
+                    ErrorType::RuntimeError,
+                )),
+            },
+            (_, Some(enclosing)) => {
+                enclosing
+                    .borrow_mut()
+                    .assign_at(distance - 1, name, value, span)
+            }
+        }
     }
+}
 
-    pub fn get(&mut self, name: Token) -> Result<&Object, LoxError> {
-        if self.values.contains_key(&name.as_string(&self.source)) {
-            println!("{:?}", name);
-            return Ok(self.values.get(&name.as_string(&self.source)).unwrap());
+impl Trace for RefCell<Environment> {
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool {
+        let Ok(environment) = self.try_borrow() else {
+            return false;
+        };
+        for value in environment.values.values() {
+            value.trace(visit);
         }
+        if let Some(enclosing) = &environment.enclosing {
+            visit(address(enclosing));
+        }
+        true
+    }
 
-        Err(LoxError::error(
-            name.span,
-            &format!("Undefined variable {}", name),
-            ErrorType::RuntimeError,
-        ))
+    fn clear(&self) {
+        if let Ok(mut environment) = self.try_borrow_mut() {
+            environment.values.clear();
+            environment.enclosing = None;
+        }
     }
 }
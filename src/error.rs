@@ -1,3 +1,5 @@
+use crate::limits::Limit;
+use crate::source_map::SourceMap;
 use crate::tokens::Span;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -11,6 +13,11 @@ pub struct LoxError {
 pub enum ErrorType {
     ParseError,
     RuntimeError,
+    /// The script ran into one of the interpreter's [`Limits`](crate::Limits).
+    LimitExceeded(Limit),
+    /// A bug in rlox rather than in the script, like the VM finding bytecode
+    /// the compiler can't have produced.
+    InternalError,
 }
 
 impl LoxError {
@@ -22,7 +29,60 @@ impl LoxError {
         }
     }
 
-    pub fn report(&self) {
-        eprintln!("[line {:?}] Error: {}", self.span, self.message);
+    pub fn limit_exceeded(span: Span, limit: Limit) -> Self {
+        Self::error(span, &limit.to_string(), ErrorType::LimitExceeded(limit))
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn error_type(&self) -> &ErrorType {
+        &self.error_type
+    }
+
+    pub fn report(&self, source_map: &SourceMap) {
+        eprintln!("{}", self.render(source_map));
+    }
+
+    /// Renders the error with its location and the offending source line,
+    /// underlining the exact span:
+    ///
+    /// ```text
+    /// error: Undefined variable 'b'.
+    ///  --> awesomeness.lox:6:11
+    ///   |
+    /// 6 | print a + b;
+    ///   |           ^
+    /// ```
+    pub fn render(&self, source_map: &SourceMap) -> String {
+        let (line, column) = source_map.location(self.span.start);
+        let text = source_map.line(line);
+
+        // spans that run past the end of the line are only underlined up to it
+        let (end_line, end_column) = source_map.location(self.span.end);
+        let end_column = if end_line == line {
+            end_column
+        } else {
+            text.chars().count() + 1
+        };
+        let carets = end_column.saturating_sub(column).max(1);
+
+        let gutter = " ".repeat(line.to_string().len());
+        format!(
+            "error: {message}\n\
+             {gutter}--> {name}:{line}:{column}\n\
+             {gutter} |\n\
+             {line} | {text}\n\
+             {gutter} | {padding}{underline}",
+            message = self.message,
+            name = source_map.name,
+            padding = " ".repeat(column - 1),
+            underline = "^".repeat(carets),
+        )
     }
 }
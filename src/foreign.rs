@@ -0,0 +1,192 @@
+use crate::error::{ErrorType, LoxError};
+use crate::function::{Arity, NativeFunction};
+use crate::interpreter::Interpreter;
+use crate::tokens::{Object, Span};
+use std::any::Any;
+use std::cell::{Ref, RefCell, RefMut};
+use std::fmt;
+use std::rc::Rc;
+
+/// A value owned by the host that scripts can hold on to and work with, like
+/// a database handle or a config struct.
+///
+/// The garbage collector can't see inside foreign objects, so functions and
+/// instances stored in one are only kept alive while scripts can reach them
+/// some other way.
+///
+/// ```
+/// use rlox::{Arity, ForeignObject, Interpreter, LoxError, Object};
+/// use std::fmt;
+///
+/// struct Counter {
+///     count: f64,
+/// }
+///
+/// impl fmt::Display for Counter {
+///     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+///         write!(f, "<counter {}>", self.count)
+///     }
+/// }
+///
+/// impl ForeignObject for Counter {
+///     fn type_name(&self) -> &str {
+///         "Counter"
+///     }
+///
+///     fn get(&self, name: &str) -> Option<Object> {
+///         (name == "count").then_some(Object::Num(self.count))
+///     }
+///
+///     fn method_arity(&self, name: &str) -> Option<Arity> {
+///         (name == "increment").then_some(Arity::Exactly(0))
+///     }
+///
+///     fn call_method(
+///         &mut self,
+///         _interpreter: &mut Interpreter,
+///         _name: &str,
+///         _arguments: &[Object],
+///     ) -> Result<Object, LoxError> {
+///         self.count += 1.0;
+///         Ok(Object::Nil)
+///     }
+/// }
+///
+/// let mut lox = rlox::Lox::new();
+/// lox.interpreter().define_global("counter", Object::foreign(Counter { count: 0.0 }));
+/// lox.eval("counter.increment(); counter.increment();").unwrap();
+///
+/// let counter = lox.interpreter().get_global("counter").unwrap();
+/// assert_eq!(counter.downcast_ref::<Counter>().unwrap().count, 2.0);
+/// ```
+pub trait ForeignObject: Any + fmt::Display {
+    /// The name of the type, used in error messages.
+    fn type_name(&self) -> &str;
+
+    /// Reads the property `name`, or returns `None` if there isn't one.
+    fn get(&self, _name: &str) -> Option<Object> {
+        None
+    }
+
+    /// Writes the property `name`. The error message is reported to the script.
+    fn set(&mut self, _name: &str, _value: Object) -> Result<(), String> {
+        Err(format!("Can't set properties on {}.", self.type_name()))
+    }
+
+    /// How many arguments the method `name` takes, or `None` if scripts can't
+    /// call `name` as a method, as in `object.name(...)`.
+    fn method_arity(&self, _name: &str) -> Option<Arity> {
+        None
+    }
+
+    /// Calls the method `name`. Only called for names `method_arity` accepts,
+    /// with as many arguments as it said.
+    fn call_method(
+        &mut self,
+        interpreter: &mut Interpreter,
+        name: &str,
+        _arguments: &[Object],
+    ) -> Result<Object, LoxError> {
+        Err(interpreter.runtime_error(&format!("Undefined method '{name}'.")))
+    }
+
+    /// Whether this is equal to `other` in a script's `==`. Objects are
+    /// always equal to themselves, whatever this returns.
+    fn equals(&self, _other: &dyn ForeignObject) -> bool {
+        false
+    }
+}
+
+impl fmt::Debug for dyn ForeignObject {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<{}>", self.type_name())
+    }
+}
+
+impl Object {
+    /// Wraps a host value so it can be handed to scripts.
+    pub fn foreign(value: impl ForeignObject) -> Object {
+        Object::Foreign(Rc::new(RefCell::new(value)))
+    }
+
+    /// Borrows the host value inside this object if it is a `T`. Returns
+    /// `None` for any other kind of object, or if it is already mutably
+    /// borrowed.
+    pub fn downcast_ref<T: ForeignObject>(&self) -> Option<Ref<'_, T>> {
+        let Object::Foreign(object) = self else {
+            return None;
+        };
+        let object = object.try_borrow().ok()?;
+        Ref::filter_map(object, |object| (object as &dyn Any).downcast_ref::<T>()).ok()
+    }
+
+    /// Like [`Object::downcast_ref`], but mutable.
+    pub fn downcast_mut<T: ForeignObject>(&self) -> Option<RefMut<'_, T>> {
+        let Object::Foreign(object) = self else {
+            return None;
+        };
+        let object = object.try_borrow_mut().ok()?;
+        RefMut::filter_map(object, |object| {
+            (object as &mut dyn Any).downcast_mut::<T>()
+        })
+        .ok()
+    }
+}
+
+fn in_use(span: Span) -> LoxError {
+    LoxError::error(
+        span,
+        "Object is already in use by the host.",
+        ErrorType::RuntimeError,
+    )
+}
+
+/// Looks up a property of a foreign object. Methods come back as natives
+/// that remember which object they were looked up on.
+pub fn get(
+    object: &Rc<RefCell<dyn ForeignObject>>,
+    name: &str,
+    span: Span,
+) -> Result<Object, LoxError> {
+    let borrowed = object.try_borrow().map_err(|_| in_use(span))?;
+
+    if let Some(value) = borrowed.get(name) {
+        return Ok(value);
+    }
+
+    if let Some(arity) = borrowed.method_arity(name) {
+        let object = Rc::clone(object);
+        let method = name.to_string();
+        let native = NativeFunction::new(
+            name,
+            arity,
+            Box::new(move |interpreter, arguments| {
+                let mut object = object.try_borrow_mut().map_err(|_| {
+                    interpreter.runtime_error("Object is already in use by the host.")
+                })?;
+                object.call_method(interpreter, &method, arguments)
+            }),
+        );
+        return Ok(Object::Native(Rc::new(native)));
+    }
+
+    Err(LoxError::error(
+        span,
+        &format!("Undefined property '{}' on {}.", name, borrowed.type_name()),
+        ErrorType::RuntimeError,
+    ))
+}
+
+/// Sets a property of a foreign object.
+pub fn set(
+    object: &Rc<RefCell<dyn ForeignObject>>,
+    name: &str,
+    value: Object,
+    span: Span,
+) -> Result<(), LoxError> {
+    object
+        .try_borrow_mut()
+        .map_err(|_| in_use(span))?
+        .set(name, value)
+        .map_err(|message| LoxError::error(span, &message, ErrorType::RuntimeError))
+}
@@ -0,0 +1,178 @@
+use crate::ast::FunctionStmt;
+use crate::class::LoxInstance;
+use crate::environment::Environment;
+use crate::error::LoxError;
+use crate::gc::{address, Heap, Trace};
+use crate::interner::Symbol;
+use crate::interpreter::{ControlFlow, Interpreter};
+use crate::tokens::Object;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+/// How many arguments a callable accepts.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Arity {
+    Exactly(usize),
+    /// Variadic, with a minimum number of arguments.
+    AtLeast(usize),
+}
+
+impl Arity {
+    pub fn accepts(self, count: usize) -> bool {
+        match self {
+            Arity::Exactly(n) => count == n,
+            Arity::AtLeast(n) => count >= n,
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Arity::Exactly(n) => write!(f, "{n}"),
+            Arity::AtLeast(n) => write!(f, "at least {n}"),
+        }
+    }
+}
+
+/// Anything that can be called with `()` from a script.
+pub trait Callable {
+    /// The number of arguments the callable expects.
+    fn arity(&self) -> Arity;
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Object>,
+    ) -> Result<Object, LoxError>;
+}
+
+/// A function declared in Lox code, together with the environment it was
+/// declared in so that it can close over the variables around it.
+pub struct LoxFunction {
+    pub name: Symbol,
+    declaration: Rc<FunctionStmt>,
+    closure: Rc<RefCell<Environment>>,
+    /// Initializers always return `this`, whatever their body does.
+    is_initializer: bool,
+}
+
+impl LoxFunction {
+    pub fn new(
+        name: Symbol,
+        declaration: Rc<FunctionStmt>,
+        closure: Rc<RefCell<Environment>>,
+        is_initializer: bool,
+    ) -> Self {
+        Self {
+            name,
+            declaration,
+            closure,
+            is_initializer,
+        }
+    }
+
+    /// Creates a copy of this method whose closure has `this` bound to `instance`.
+    pub fn bind(&self, instance: Rc<RefCell<LoxInstance>>, heap: &mut Heap) -> Rc<LoxFunction> {
+        let mut environment = Environment::with_enclosing(Rc::clone(&self.closure));
+        environment.define(Symbol::THIS, Object::Instance(instance));
+
+        let closure = heap.manage(RefCell::new(environment));
+        heap.manage(LoxFunction::new(
+            self.name,
+            Rc::clone(&self.declaration),
+            closure,
+            self.is_initializer,
+        ))
+    }
+}
+
+impl Callable for LoxFunction {
+    fn arity(&self) -> Arity {
+        Arity::Exactly(self.declaration.params.len())
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Object>,
+    ) -> Result<Object, LoxError> {
+        // every call gets its own environment so recursion works
+        let mut environment = Environment::with_enclosing(Rc::clone(&self.closure));
+        for (param, argument) in self.declaration.params.iter().zip(arguments) {
+            interpreter.trace_define(param.symbol, &argument, param.span);
+            environment.define(param.symbol, argument);
+        }
+
+        let flow = interpreter.execute_block(&self.declaration.body, environment)?;
+
+        if self.is_initializer {
+            return self
+                .closure
+                .borrow()
+                .get(Symbol::THIS, self.declaration.name.span);
+        }
+
+        match flow {
+            ControlFlow::Return(value) => Ok(value),
+            ControlFlow::Normal => Ok(Object::Nil),
+        }
+    }
+}
+
+impl Trace for LoxFunction {
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool {
+        visit(address(&self.closure));
+        true
+    }
+
+    // the closure is what gets cleared
+    fn clear(&self) {}
+}
+
+// closures can (indirectly) contain themselves, so don't recurse into them
+impl fmt::Debug for LoxFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<fn {}>", self.name)
+    }
+}
+
+/// The signature of functions implemented in Rust.
+pub type NativeFn = dyn Fn(&mut Interpreter, &[Object]) -> Result<Object, LoxError>;
+
+/// A function implemented by the host in Rust, see [`Interpreter::define_native`].
+pub struct NativeFunction {
+    pub name: String,
+    arity: Arity,
+    function: Box<NativeFn>,
+}
+
+impl NativeFunction {
+    pub fn new(name: &str, arity: Arity, function: Box<NativeFn>) -> Self {
+        Self {
+            name: name.to_string(),
+            arity,
+            function,
+        }
+    }
+}
+
+impl Callable for NativeFunction {
+    fn arity(&self) -> Arity {
+        self.arity
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Object>,
+    ) -> Result<Object, LoxError> {
+        (self.function)(interpreter, &arguments)
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
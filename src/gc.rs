@@ -0,0 +1,217 @@
+use crate::interner;
+use crate::tokens::Object;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::{Rc, Weak};
+
+/// The heap starts collecting once it holds this many objects.
+const INITIAL_THRESHOLD: usize = 1024;
+
+/// Something allocated on the [`Heap`] that can refer to other objects on it.
+pub(crate) trait Trace {
+    /// Calls `visit` with the [`address`] of every object this one refers
+    /// to. Returns `false` if it can't look inside right now because it is
+    /// being changed, in which case the collection is called off, since what
+    /// it refers to can't be known.
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool;
+
+    /// Drops every reference this object holds, breaking the cycles it is
+    /// part of so that they get freed.
+    fn clear(&self);
+}
+
+/// Identifies an object on the heap.
+pub(crate) fn address<T: ?Sized>(object: &Rc<T>) -> usize {
+    Rc::as_ptr(object) as *const () as usize
+}
+
+impl Object {
+    /// Whether this value refers to an object on the heap.
+    pub(crate) fn is_managed(&self) -> bool {
+        matches!(
+            self,
+            Object::Function(_)
+                | Object::Class(_)
+                | Object::Instance(_)
+                | Object::Closure(_)
+                | Object::BoundMethod(_)
+                | Object::VmClass(_)
+                | Object::VmInstance(_)
+        )
+    }
+
+    /// Visits the object on the heap this value refers to, if there is one.
+    pub(crate) fn trace(&self, visit: &mut dyn FnMut(usize)) {
+        match self {
+            Object::Function(function) => visit(address(function)),
+            Object::Class(class) => visit(address(class)),
+            Object::Instance(instance) => visit(address(instance)),
+            Object::Closure(closure) => visit(address(closure)),
+            Object::BoundMethod(bound) => visit(address(bound)),
+            Object::VmClass(class) => visit(address(class)),
+            Object::VmInstance(instance) => visit(address(instance)),
+            // strings are handles into the string table, which is swept
+            // separately since they can't refer to anything. What natives
+            // and foreign objects hold on to can't be seen from here
+            Object::Num(_)
+            | Object::Str(_)
+            | Object::Nil
+            | Object::Bool(_)
+            | Object::Native(_)
+            | Object::Foreign(_) => {}
+        }
+    }
+}
+
+/// What the garbage collector has done so far.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct GcStats {
+    /// How many objects have been allocated on the heap.
+    pub allocated: u64,
+    /// How many objects are still alive.
+    pub live: usize,
+    /// How many times the collector has run.
+    pub collections: u64,
+    /// How many unreachable objects the collector has freed.
+    pub collected: u64,
+    /// How many distinct strings were alive after the last collection.
+    pub strings: usize,
+}
+
+impl fmt::Display for GcStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} objects allocated, {} live, {} freed by {} collections, {} strings",
+            self.allocated, self.live, self.collected, self.collections, self.strings
+        )
+    }
+}
+
+/// A precise mark-and-sweep collector for the environments, functions,
+/// classes, instances and closures that scripts create.
+///
+/// Objects are handed out as `Rc` handles, so most of them are freed as soon
+/// as nothing refers to them anymore. That leaves cycles, like a function
+/// stored in the environment it closes over, or an instance with a field
+/// pointing back at itself, which collections find:
+///
+/// 1. The interpreter lists its roots: the globals, the current environment
+///    and the ones saved by the blocks and calls it is inside of, the values
+///    of expressions it is still evaluating, and the stack, call frames and
+///    open upvalues of every VM that is running.
+/// 2. Everything reachable from the roots is marked.
+/// 3. Whatever isn't marked is garbage, and has its references cleared,
+///    which frees it.
+///
+/// Only the interpreter knows its roots, so it collects at safepoints where
+/// every value it is using is reachable from one: the tree-walker before
+/// each statement and expression, and the VM once the object it allocated is
+/// on its stack. Values only Rust code holds on to, like the host or what
+/// natives and foreign objects capture, aren't roots.
+///
+/// Strings live in the interned string table instead, which every
+/// collection sweeps of the strings that have been freed.
+#[derive(Default)]
+pub struct Heap {
+    objects: Vec<Weak<dyn Trace>>,
+    stats: GcStats,
+    /// Collect after every allocation, to shake out objects that are used
+    /// without being reachable from a root.
+    stress: bool,
+    /// Whether anything has been allocated since the last collection.
+    allocated_since: bool,
+    /// Collect when this many objects have been allocated.
+    threshold: usize,
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        Self {
+            threshold: INITIAL_THRESHOLD,
+            ..Self::default()
+        }
+    }
+
+    /// Allocates `object` on the heap. It isn't collected until the next
+    /// safepoint, so it doesn't have to be reachable until then.
+    pub(crate) fn manage<T: Trace + 'static>(&mut self, object: T) -> Rc<T> {
+        let object = Rc::new(object);
+        let weak: Weak<dyn Trace> = Rc::downgrade(&object) as Weak<dyn Trace>;
+        self.objects.push(weak);
+        self.stats.allocated += 1;
+        self.allocated_since = true;
+        object
+    }
+
+    /// Whether it is time to collect garbage.
+    pub(crate) fn wants_collection(&self) -> bool {
+        (self.stress && self.allocated_since) || self.objects.len() >= self.threshold
+    }
+
+    pub fn set_stress(&mut self, stress: bool) {
+        self.stress = stress;
+    }
+
+    pub fn stats(&self) -> GcStats {
+        GcStats {
+            live: self
+                .objects
+                .iter()
+                .filter(|object| object.strong_count() > 0)
+                .count(),
+            strings: interner::live_strings(),
+            ..self.stats
+        }
+    }
+
+    /// Frees every object that can't be reached from `roots`, which are the
+    /// [`address`]es of the objects the interpreter is using directly.
+    pub(crate) fn collect(&mut self, roots: Vec<usize>) {
+        let objects: Vec<Rc<dyn Trace>> = self.objects.iter().filter_map(Weak::upgrade).collect();
+        let index: HashMap<usize, usize> = objects
+            .iter()
+            .enumerate()
+            .map(|(i, object)| (address(object), i))
+            .collect();
+
+        let mut marked = vec![false; objects.len()];
+        let mut pending = Vec::new();
+        let mut mark = |object: usize, pending: &mut Vec<usize>| {
+            if let Some(&i) = index.get(&object) {
+                if !marked[i] {
+                    marked[i] = true;
+                    pending.push(i);
+                }
+            }
+        };
+        for root in roots {
+            mark(root, &mut pending);
+        }
+        let mut children = Vec::new();
+        while let Some(i) = pending.pop() {
+            if !objects[i].trace(&mut |child| children.push(child)) {
+                return;
+            }
+            for child in children.drain(..) {
+                mark(child, &mut pending);
+            }
+        }
+
+        let mut survivors = Vec::with_capacity(objects.len());
+        for (object, marked) in objects.iter().zip(marked) {
+            if marked {
+                survivors.push(Rc::downgrade(object));
+            } else {
+                object.clear();
+                self.stats.collected += 1;
+            }
+        }
+        // the garbage is freed when `objects` is dropped
+        self.objects = survivors;
+        interner::sweep_strings();
+        self.stats.collections += 1;
+        self.allocated_since = false;
+        self.threshold = (self.objects.len() * 2).max(INITIAL_THRESHOLD);
+    }
+}
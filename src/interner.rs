@@ -0,0 +1,224 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasher, Hash, Hasher, RandomState};
+use std::ops::Deref;
+use std::rc::{Rc, Weak};
+
+thread_local! {
+    static SYMBOLS: RefCell<Symbols> = RefCell::new(Symbols::new());
+    static STRINGS: RefCell<Strings> = RefCell::new(Strings::default());
+}
+
+/// An interned identifier. Names are turned into symbols once, when code is
+/// parsed, so that looking up variables, fields and methods only hashes and
+/// compares a number.
+///
+/// ```
+/// use rlox::Symbol;
+///
+/// let name = Symbol::intern("counter");
+/// assert_eq!(name, Symbol::intern("counter"));
+/// assert_eq!(name.as_str(), "counter");
+/// ```
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    pub const INIT: Symbol = Symbol(0);
+    pub const THIS: Symbol = Symbol(1);
+    pub const SUPER: Symbol = Symbol(2);
+
+    /// Returns the symbol for `name`, making a new one if it hasn't been
+    /// seen before.
+    ///
+    /// Symbols are never freed: the first time a name is interned, a copy of
+    /// it is leaked so that [`Symbol::as_str`] can hand out `&'static str`s.
+    /// That is fine for the identifiers in source code, of which there are
+    /// only so many, but hosts shouldn't intern names that come from
+    /// unbounded input, since each new one costs memory for the rest of the
+    /// process.
+    pub fn intern(name: &str) -> Symbol {
+        SYMBOLS.with(|symbols| symbols.borrow_mut().intern(name))
+    }
+
+    /// The name this symbol stands for.
+    pub fn as_str(self) -> &'static str {
+        SYMBOLS.with(|symbols| symbols.borrow().names[self.0 as usize])
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Symbol({:?})", self.as_str())
+    }
+}
+
+/// The names of all symbols. There are only as many as there are distinct
+/// names in the code that has been parsed, so they are leaked rather than
+/// freed, see [`Symbol::intern`].
+struct Symbols {
+    names: Vec<&'static str>,
+    indices: HashMap<&'static str, Symbol>,
+}
+
+impl Symbols {
+    fn new() -> Self {
+        let mut symbols = Self {
+            names: Vec::new(),
+            indices: HashMap::new(),
+        };
+        // in the order of the constants on `Symbol`
+        for name in ["init", "this", "super"] {
+            symbols.intern(name);
+        }
+        symbols
+    }
+
+    fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(&symbol) = self.indices.get(name) {
+            return symbol;
+        }
+
+        let symbol = Symbol(self.names.len() as u32);
+        let name: &'static str = Box::leak(name.into());
+        self.names.push(name);
+        self.indices.insert(name, symbol);
+        symbol
+    }
+}
+
+/// A string value. Strings are interned, so there is only ever one copy of
+/// each, and comparing two strings compares pointers.
+///
+/// ```
+/// use rlox::LoxString;
+///
+/// let greeting = LoxString::new("hello");
+/// assert_eq!(greeting, LoxString::from(format!("hel{}", "lo")));
+/// assert_eq!(&*greeting, "hello");
+/// ```
+#[derive(Clone)]
+pub struct LoxString(Rc<str>);
+
+impl LoxString {
+    /// Returns the string with these contents, creating it if it doesn't
+    /// exist yet.
+    pub fn new(text: &str) -> Self {
+        STRINGS.with(|strings| LoxString(strings.borrow_mut().intern(text)))
+    }
+}
+
+impl Deref for LoxString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq for LoxString {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for LoxString {}
+
+impl Hash for LoxString {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Rc::as_ptr(&self.0).cast::<u8>().hash(state);
+    }
+}
+
+impl fmt::Display for LoxString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self)
+    }
+}
+
+impl fmt::Debug for LoxString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", &**self)
+    }
+}
+
+impl From<&str> for LoxString {
+    fn from(text: &str) -> Self {
+        LoxString::new(text)
+    }
+}
+
+impl From<String> for LoxString {
+    fn from(text: String) -> Self {
+        LoxString::new(&text)
+    }
+}
+
+/// Forgets the strings that have been freed since the last sweep.
+pub(crate) fn sweep_strings() {
+    STRINGS.with(|strings| strings.borrow_mut().clean());
+}
+
+/// How many distinct strings are alive.
+pub(crate) fn live_strings() -> usize {
+    STRINGS.with(|strings| {
+        let strings = strings.borrow();
+        strings
+            .buckets
+            .values()
+            .flatten()
+            .filter(|string| string.strong_count() > 0)
+            .count()
+    })
+}
+
+/// All the strings that are alive. Unlike symbols, strings are made while
+/// scripts run and can be freed, so only weak references are kept here.
+#[derive(Default)]
+struct Strings {
+    /// Strings bucketed by the hash of their contents.
+    buckets: HashMap<u64, Vec<Weak<str>>>,
+    hasher: RandomState,
+    /// How many strings have been added since the buckets were last cleaned.
+    added: usize,
+    len: usize,
+}
+
+impl Strings {
+    fn intern(&mut self, text: &str) -> Rc<str> {
+        let bucket = self.buckets.entry(self.hasher.hash_one(text)).or_default();
+        for weak in bucket.iter() {
+            if let Some(string) = weak.upgrade().filter(|string| **string == *text) {
+                return string;
+            }
+        }
+
+        let string: Rc<str> = Rc::from(text);
+        bucket.push(Rc::downgrade(&string));
+        self.added += 1;
+        self.len += 1;
+
+        // the memory of freed strings is only given back once the weak
+        // references to them are gone, so clean up every so often
+        if self.added > self.len / 2 + 1024 {
+            self.clean();
+        }
+        string
+    }
+
+    fn clean(&mut self) {
+        self.buckets.retain(|_, bucket| {
+            bucket.retain(|weak| weak.strong_count() > 0);
+            !bucket.is_empty()
+        });
+        self.len = self.buckets.values().map(Vec::len).sum();
+        self.added = 0;
+    }
+}
@@ -1,50 +1,555 @@
-use crate::{ast::*, environment::*, error::*, tokens::*};
+use crate::foreign::{self, ForeignObject};
+use crate::gc::{address, GcStats, Heap};
+use crate::interner::{LoxString, Symbol};
+use crate::limits::{Limit, Limits, NESTING_PER_CALL};
+use crate::permissions::Permissions;
+use crate::resolver::Resolver;
+use crate::trace::Tracer;
+use crate::vm::{SuspendedVm, Vm};
+use crate::{
+    ast::*, class::*, compiler, environment::*, error::*, function::*, natives, tokens::*,
+};
+use std::cell::{Ref, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::Instant;
 
-pub struct Interpreter<'a> {
-    environment: Environment<'a>,
-    source: &'a str,
+/// How an [`Interpreter`] runs the code it is given.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// Walk the syntax tree.
+    #[default]
+    Tree,
+    /// Compile to bytecode and run it on a virtual machine.
+    Vm,
 }
 
-impl<'a> Interpreter<'a> {
-    pub fn new(source: &'a str) -> Self {
-        Self {
-            environment: Environment::new(source),
-            source,
+/// What happens after a statement finishes executing.
+#[derive(Debug)]
+pub enum ControlFlow {
+    /// Carry on with the next statement.
+    Normal,
+    /// A `return` is unwinding to the enclosing function call.
+    Return(Object),
+}
+
+pub struct Interpreter {
+    /// The outermost scope, where natives are registered.
+    globals: Rc<RefCell<Environment>>,
+    environment: Rc<RefCell<Environment>>,
+    /// The environments of the blocks and calls the current one is running
+    /// inside of, restored as each of them finishes.
+    environments: Vec<Rc<RefCell<Environment>>>,
+    /// Values the tree-walker is holding on to while it evaluates the rest
+    /// of an expression, like the callee and arguments of a call.
+    temporaries: Vec<Object>,
+    /// VMs waiting for a native they called to return.
+    suspended: Vec<SuspendedVm>,
+    /// How many scopes out each local variable is, worked out by the
+    /// [`Resolver`] before code runs.
+    locals: HashMap<*const Identifier, usize>,
+    /// All the source code this interpreter has been given. Tokens only store
+    /// spans, so functions defined by earlier code need it to stay around.
+    source: String,
+    /// Where environments, functions, classes and instances are allocated.
+    heap: Heap,
+    /// Logs what the interpreter does, if tracing is turned on.
+    tracer: Option<Tracer>,
+    backend: Backend,
+    /// Whether any code has run yet, after which the backend can't change.
+    has_run: bool,
+    /// The span of the call being made, so natives can report errors at it.
+    call_span: Span,
+    limits: Limits,
+    /// What natives are allowed to do on behalf of scripts.
+    permissions: Permissions,
+    /// How much of each limit has been used up since the limits were set.
+    steps: u64,
+    call_depth: usize,
+    /// How many statements and expressions are being executed inside each
+    /// other, which is how deep the tree-walker has recursed.
+    depth: usize,
+    allocated: usize,
+    deadline: Option<Instant>,
+}
+
+impl Interpreter {
+    /// Creates an interpreter whose scripts can do what `permissions` allow,
+    /// which is nothing for [`Permissions::none`].
+    pub fn new(source: &str, permissions: Permissions) -> Self {
+        let mut heap = Heap::new();
+        let globals = heap.manage(RefCell::new(Environment::new()));
+
+        let mut interpreter = Self {
+            environment: Rc::clone(&globals),
+            environments: vec![],
+            temporaries: vec![],
+            suspended: vec![],
+            locals: HashMap::new(),
+            globals,
+            source: source.to_string(),
+            heap,
+            tracer: None,
+            backend: Backend::default(),
+            has_run: false,
+            call_span: Span::from(0..0),
+            limits: Limits::default(),
+            permissions,
+            steps: 0,
+            call_depth: 0,
+            depth: 0,
+            allocated: 0,
+            deadline: None,
+        };
+        natives::define_globals(&mut interpreter);
+        interpreter
+    }
+
+    /// Makes a Rust function callable from scripts as a global called `name`.
+    ///
+    /// ```
+    /// use rlox::{Arity, Interpreter, Object, Permissions};
+    ///
+    /// let mut interpreter = Interpreter::new("", Permissions::none());
+    /// interpreter.define_native("sum", Arity::AtLeast(0), |interpreter, arguments| {
+    ///     let mut total = 0.0;
+    ///     for argument in arguments {
+    ///         match argument {
+    ///             Object::Num(n) => total += n,
+    ///             _ => return Err(interpreter.runtime_error("sum() only takes numbers.")),
+    ///         }
+    ///     }
+    ///     Ok(Object::Num(total))
+    /// });
+    /// ```
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: Arity,
+        function: impl Fn(&mut Interpreter, &[Object]) -> Result<Object, LoxError> + 'static,
+    ) {
+        let native = NativeFunction::new(name, arity, Box::new(function));
+        self.globals
+            .borrow_mut()
+            .define(Symbol::intern(name), Object::Native(Rc::new(native)));
+    }
+
+    /// Defines (or redefines) a global variable.
+    pub fn define_global(&mut self, name: &str, value: Object) {
+        self.globals
+            .borrow_mut()
+            .define(Symbol::intern(name), value);
+    }
+
+    /// Returns the value of a global variable, if it is defined.
+    pub fn get_global(&self, name: &str) -> Option<Object> {
+        self.globals
+            .borrow()
+            .get(Symbol::intern(name), Span::from(0..0))
+            .ok()
+    }
+
+    /// Borrows the host value of type `T` inside `value`, or returns an error
+    /// pointing at the current call if it is anything else.
+    pub fn expect_foreign<'value, T: ForeignObject>(
+        &self,
+        value: &'value Object,
+        type_name: &str,
+    ) -> Result<Ref<'value, T>, LoxError> {
+        value
+            .downcast_ref()
+            .ok_or_else(|| self.runtime_error(&format!("Expected {type_name} but got {value}.")))
+    }
+
+    /// Creates a runtime error pointing at the call currently being made.
+    /// Meant for natives, which don't know where they were called from.
+    pub fn runtime_error(&self, message: &str) -> LoxError {
+        LoxError::error(self.call_span, message, ErrorType::RuntimeError)
+    }
+
+    /// Appends more code to the source, returning the offset it starts at.
+    /// Code added this way should be scanned from that offset so that spans
+    /// keep pointing into `source`.
+    pub fn append_source(&mut self, source: &str) -> usize {
+        let offset = self.source.len();
+        self.source.push_str(source);
+        offset
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Returns the variables defined in the current scope.
+    pub fn bindings(&self) -> Vec<(String, Object)> {
+        self.environment.borrow().bindings()
+    }
+
+    /// Turns tracing on, or off with `None`. Only [`Backend::Tree`] is
+    /// traced; the VM runs without logging anything.
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
+    /// Chooses how [`Interpreter::interpret`] runs code.
+    ///
+    /// Each backend has its own kind of functions, classes and instances,
+    /// which the other can't call or look inside, so the backend can only be
+    /// changed before any code has run.
+    ///
+    /// ```
+    /// use rlox::{Backend, Lox};
+    ///
+    /// let mut lox = Lox::new();
+    /// lox.interpreter().set_backend(Backend::Vm).unwrap();
+    /// lox.eval("fun f() {}").unwrap();
+    /// assert!(lox.interpreter().set_backend(Backend::Tree).is_err());
+    /// ```
+    pub fn set_backend(&mut self, backend: Backend) -> Result<(), String> {
+        if self.has_run && backend != self.backend {
+            return Err(format!(
+                "can't switch to the {backend:?} backend after code has run"
+            ));
         }
+        self.backend = backend;
+        Ok(())
     }
 
-    pub fn interpret(&mut self, statements: &Vec<Stmt>) -> Result<(), LoxError> {
-        for statement in statements {
-            if let Err(e) = self.execute(statement) {
-                e.report();
-                break;
+    /// What the garbage collector has done so far.
+    pub fn gc_stats(&self) -> GcStats {
+        self.heap.stats()
+    }
+
+    /// Frees everything scripts can't reach anymore. This happens on its own
+    /// as scripts allocate, but hosts might want to do it at quiet moments.
+    ///
+    /// Values the host or its natives hold on to aren't kept alive by that
+    /// alone, so functions and instances they want to keep using have to stay
+    /// reachable from a global.
+    pub fn collect_garbage(&mut self) {
+        let mut roots = vec![address(&self.globals), address(&self.environment)];
+        roots.extend(self.environments.iter().map(address));
+        let mut visit = |object| roots.push(object);
+        for value in &self.temporaries {
+            value.trace(&mut visit);
+        }
+        for vm in &self.suspended {
+            vm.trace(&mut visit);
+        }
+        self.heap.collect(roots);
+    }
+
+    /// Collects garbage if enough has been allocated since the last
+    /// collection. Only called where everything in use is reachable from a
+    /// root.
+    pub(crate) fn safepoint(&mut self) {
+        if self.heap.wants_collection() {
+            self.collect_garbage();
+        }
+    }
+
+    /// Keeps a VM's stack where the collector can find it while the VM waits
+    /// for a native, which might run more code.
+    pub(crate) fn suspend(&mut self, vm: SuspendedVm) {
+        self.suspended.push(vm);
+    }
+
+    /// Hands back the stack of the VM suspended last.
+    pub(crate) fn resume(&mut self) -> Option<SuspendedVm> {
+        self.suspended.pop()
+    }
+
+    /// Collects garbage on every allocation. This is very slow, and only
+    /// meant for finding bugs in the collector.
+    pub fn set_gc_stress(&mut self, stress: bool) {
+        self.heap.set_stress(stress);
+    }
+
+    /// Limits the resources scripts can use from now on. Whatever was used up
+    /// under the previous limits is forgotten. The timeout applies to each
+    /// run separately, and starts when the run does.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+        self.steps = 0;
+        self.allocated = 0;
+    }
+
+    /// Starts the clock for the timeout, at the beginning of a run.
+    pub(crate) fn start_timeout(&mut self) {
+        self.deadline = self.limits.timeout.map(|timeout| Instant::now() + timeout);
+    }
+
+    /// Counts one evaluated node against the step limit, and every so often
+    /// checks the clock.
+    pub(crate) fn step(&mut self, span: Span) -> Result<(), LoxError> {
+        self.steps += 1;
+
+        if self.limits.max_steps.is_some_and(|max| self.steps > max) {
+            return Err(LoxError::limit_exceeded(span, Limit::Steps));
+        }
+        // reading the clock is slow compared to evaluating a node
+        if self.steps.is_multiple_of(1024)
+            && self
+                .deadline
+                .is_some_and(|deadline| Instant::now() > deadline)
+        {
+            return Err(LoxError::limit_exceeded(span, Limit::Time));
+        }
+        Ok(())
+    }
+
+    pub fn permissions(&self) -> &Permissions {
+        &self.permissions
+    }
+
+    /// The span of the call currently being made.
+    pub(crate) fn call_span(&self) -> Span {
+        self.call_span
+    }
+
+    /// Sets the span natives report errors at, returning the previous one.
+    pub(crate) fn set_call_span(&mut self, span: Span) -> Span {
+        std::mem::replace(&mut self.call_span, span)
+    }
+
+    pub(crate) fn heap(&mut self) -> &mut Heap {
+        &mut self.heap
+    }
+
+    pub(crate) fn globals(&self) -> Rc<RefCell<Environment>> {
+        Rc::clone(&self.globals)
+    }
+
+    pub(crate) fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Counts `bytes` against the memory limit.
+    pub(crate) fn allocate(&mut self, bytes: usize, span: Span) -> Result<(), LoxError> {
+        self.allocated += bytes;
+
+        if self
+            .limits
+            .max_memory
+            .is_some_and(|max| self.allocated > max)
+        {
+            return Err(LoxError::limit_exceeded(span, Limit::Memory));
+        }
+        Ok(())
+    }
+
+    /// Runs a program with the current backend, stopping at the first
+    /// runtime error. With [`Backend::Vm`] the program is compiled first, so
+    /// a compile error means none of it runs.
+    pub fn interpret(&mut self, statements: &[Stmt]) -> Result<(), LoxError> {
+        self.start_timeout();
+        self.run_statements(statements)
+    }
+
+    /// Evaluates a single expression with the current backend.
+    pub fn interpret_expression(&mut self, expr: &Expr) -> Result<Object, LoxError> {
+        self.start_timeout();
+        self.run_expression(expr)
+    }
+
+    /// Like [`Interpreter::interpret`], but part of a run that has already
+    /// started.
+    pub(crate) fn run_statements(&mut self, statements: &[Stmt]) -> Result<(), LoxError> {
+        self.has_run = true;
+        match self.backend {
+            Backend::Tree => {
+                Resolver::new(&mut self.locals).resolve(statements);
+                for statement in statements {
+                    self.execute(statement)?;
+                }
+            }
+            Backend::Vm => {
+                let script = compiler::compile(&self.source, statements)?;
+                Vm::new(self).run(script)?;
             }
         }
         Ok(())
     }
 
-    pub fn execute(&mut self, stmt: &Stmt) -> Result<(), LoxError> {
-        // statements dont return anything so we return an empty ok
-        stmt.accept(self)
+    /// Like [`Interpreter::interpret_expression`], but part of a run that has
+    /// already started.
+    pub(crate) fn run_expression(&mut self, expr: &Expr) -> Result<Object, LoxError> {
+        self.has_run = true;
+        match self.backend {
+            Backend::Tree => {
+                Resolver::new(&mut self.locals).resolve_expression(expr);
+                let temporaries = self.temporaries.len();
+                let result = self.evaluate(expr);
+                self.temporaries.truncate(temporaries);
+                result
+            }
+            Backend::Vm => {
+                let script = compiler::compile_expression(expr)?;
+                Vm::new(self).run(script)
+            }
+        }
     }
 
-    pub fn evaluate(&mut self, expr: &Expr) -> Result<Object, LoxError> {
-        expr.accept(self)
+    pub(crate) fn execute(&mut self, stmt: &Stmt) -> Result<ControlFlow, LoxError> {
+        // an empty block has no span, so report it at the enclosing call
+        let span = stmt.span().unwrap_or(self.call_span);
+        self.step(span)?;
+        self.nest(span)?;
+        self.safepoint();
+        let temporaries = self.temporaries.len();
+
+        let result = if self.tracer.is_none() {
+            // statements dont produce values, but a `return` has to be passed up
+            stmt.accept(self)
+        } else {
+            if let Some(tracer) = &mut self.tracer {
+                tracer.statement(&self.source, stmt);
+                tracer.enter();
+            }
+            let result = stmt.accept(self);
+            if let Some(tracer) = &mut self.tracer {
+                tracer.exit();
+            }
+            result
+        };
+
+        self.temporaries.truncate(temporaries);
+        self.depth -= 1;
+        result
+    }
+
+    pub(crate) fn evaluate(&mut self, expr: &Expr) -> Result<Object, LoxError> {
+        self.step(expr.span())?;
+        self.nest(expr.span())?;
+        self.safepoint();
+        let temporaries = self.temporaries.len();
+
+        let result = if self.tracer.is_none() {
+            expr.accept(self)
+        } else {
+            if let Some(tracer) = &mut self.tracer {
+                tracer.enter();
+            }
+            let result = expr.accept(self);
+            if let Some(tracer) = &mut self.tracer {
+                tracer.exit();
+                if let Ok(value) = &result {
+                    tracer.expression(&self.source, expr, value);
+                }
+            }
+            result
+        };
+
+        // whatever the subexpressions held on to has been used up, but the
+        // value itself might be needed until the enclosing expression is done
+        self.temporaries.truncate(temporaries);
+        if let Ok(value) = &result {
+            if value.is_managed() {
+                self.temporaries.push(value.clone());
+            }
+        }
+        self.depth -= 1;
+        result
+    }
+
+    /// Goes one statement or expression deeper, failing before the Rust stack
+    /// the tree-walker recurses on can run out. How deep that is depends on
+    /// how nested function bodies are as well as on how deep calls are, so
+    /// counting calls alone isn't enough.
+    fn nest(&mut self, span: Span) -> Result<(), LoxError> {
+        if self
+            .limits
+            .max_call_depth
+            .is_some_and(|max| self.depth >= max * NESTING_PER_CALL)
+        {
+            return Err(LoxError::limit_exceeded(span, Limit::CallDepth));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Defines a variable in the current scope.
+    fn define(&mut self, name: Symbol, value: Object, span: Span) {
+        self.trace_define(name, &value, span);
+        self.environment.borrow_mut().define(name, value);
+    }
+
+    /// Tells the tracer about a variable defined somewhere other than the
+    /// current scope.
+    pub(crate) fn trace_define(&mut self, name: Symbol, value: &Object, span: Span) {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.define(&self.source, name.as_str(), value, span);
+        }
+    }
+
+    /// Executes `statements` inside `environment`, restoring the current
+    /// environment afterwards even if one of the statements fails.
+    pub(crate) fn execute_block(
+        &mut self,
+        statements: &[Stmt],
+        environment: Environment,
+    ) -> Result<ControlFlow, LoxError> {
+        self.allocate(std::mem::size_of::<Environment>(), self.call_span)?;
+        let environment = self.heap.manage(RefCell::new(environment));
+        let previous = std::mem::replace(&mut self.environment, environment);
+        self.environments.push(previous);
+
+        let result = self.execute_statements(statements);
+
+        if let Some(previous) = self.environments.pop() {
+            self.environment = previous;
+        }
+        result
+    }
+
+    fn execute_statements(&mut self, statements: &[Stmt]) -> Result<ControlFlow, LoxError> {
+        for statement in statements {
+            if let ControlFlow::Return(value) = self.execute(statement)? {
+                return Ok(ControlFlow::Return(value));
+            }
+        }
+        Ok(ControlFlow::Normal)
     }
 }
 
-impl ExprVisitor<Object> for Interpreter<'_> {
+impl ExprVisitor<Object> for Interpreter {
+    fn visit_assign_expr(&mut self, assign: &AssignExpr) -> Result<Object, LoxError> {
+        let value = self.evaluate(&assign.value)?;
+        let name = assign.name;
+        match self.locals.get(&(&assign.name as *const Identifier)) {
+            Some(&distance) => self.environment.borrow_mut().assign_at(
+                distance,
+                name.symbol,
+                value.clone(),
+                name.span,
+            )?,
+            None => self
+                .globals
+                .borrow_mut()
+                .assign(name.symbol, value.clone(), name.span)?,
+        }
+        // assignment is an expression, so it evaluates to the assigned value
+        Ok(value)
+    }
+
     fn visit_unary_expr(&mut self, unary: &UnaryExpr) -> Result<Object, LoxError> {
         let right = self.evaluate(&unary.right)?;
 
         match unary.operator.token_type {
-            TokenType::Minus | TokenType::Bang => match right {
+            TokenType::Minus => match right {
                 Object::Num(n) => Ok(Object::Num(-n)),
+                _ => Err(LoxError::error(
+                    unary.right.span(),
+                    "Cannot negate non-numeric value",
+                    ErrorType::RuntimeError,
+                )),
+            },
+            TokenType::Bang => match right {
                 Object::Bool(x) => Ok(Object::Bool(!x)),
                 // no truthiness or falsiness. hooray!
                 _ => Err(LoxError::error(
-                    Span::from(0..1),
-                    "Cannot negate non-numeric or non-boolean value",
+                    unary.right.span(),
+                    "Cannot negate non-boolean value",
                     ErrorType::RuntimeError,
                 )),
             },
@@ -54,15 +559,41 @@ impl ExprVisitor<Object> for Interpreter<'_> {
     fn visit_literal_expr(&mut self, literal: &LiteralExpr) -> Result<Object, LoxError> {
         Ok(literal.value.clone())
     }
+    fn visit_logical_expr(&mut self, logical: &LogicalExpr) -> Result<Object, LoxError> {
+        let left = self.evaluate(&logical.left)?;
+        let left_is_true = is_true(&left, logical.left.span())?;
+
+        // short-circuit: if the left operand decides the result, the right one
+        // is never evaluated and the left value is returned as-is
+        match logical.operator.token_type {
+            TokenType::Keyword(KeywordType::Or) if left_is_true => Ok(left),
+            TokenType::Keyword(KeywordType::And) if !left_is_true => Ok(left),
+            _ => self.evaluate(&logical.right),
+        }
+    }
+
     fn visit_binary_expr(&mut self, binary: &BinaryExpr) -> Result<Object, LoxError> {
         let left = self.evaluate(&binary.left)?;
         let right = self.evaluate(&binary.right)?;
 
+        // equality works between any two values, everything else wants numbers
+        match binary.operator.token_type {
+            TokenType::EqualEqual => return Ok(Object::Bool(left == right)),
+            TokenType::BangEqual => return Ok(Object::Bool(left != right)),
+            TokenType::Plus => {
+                if let (Object::Str(l), Object::Str(r)) = (&left, &right) {
+                    self.allocate(l.len() + r.len(), binary.operator.span)?;
+                    return Ok(Object::Str(LoxString::from(format!("{l}{r}"))));
+                }
+            }
+            _ => {}
+        }
+
         let left_num = match left {
             Object::Num(n) => n,
             _ => {
                 return Err(LoxError::error(
-                    Span::from(0..1),
+                    binary.left.span(),
                     "Left operand is not a number",
                     ErrorType::RuntimeError,
                 ))
@@ -73,7 +604,7 @@ impl ExprVisitor<Object> for Interpreter<'_> {
             Object::Num(n) => n,
             _ => {
                 return Err(LoxError::error(
-                    Span::from(0..1),
+                    binary.right.span(),
                     "Right operand is not a number",
                     ErrorType::RuntimeError,
                 ))
@@ -89,46 +620,285 @@ impl ExprVisitor<Object> for Interpreter<'_> {
             TokenType::GreaterEqual => Ok(Object::Bool(left_num >= right_num)),
             TokenType::Less => Ok(Object::Bool(left_num < right_num)),
             TokenType::LessEqual => Ok(Object::Bool(left_num <= right_num)),
-            TokenType::EqualEqual => Ok(Object::Bool(left_num == right_num)),
-            TokenType::BangEqual => Ok(Object::Bool(left_num != right_num)),
             _ => panic!("Something has gone very wrong in the interpreter..."),
         }
     }
+    fn visit_call_expr(&mut self, call: &CallExpr) -> Result<Object, LoxError> {
+        let callee = self.evaluate(&call.callee)?;
+
+        let mut arguments = Vec::with_capacity(call.arguments.len());
+        for argument in &call.arguments {
+            arguments.push(self.evaluate(argument)?);
+        }
+
+        let function: &dyn Callable = match &callee {
+            Object::Function(function) => function.as_ref(),
+            Object::Native(native) => native.as_ref(),
+            Object::Class(class) => class,
+            _ => {
+                return Err(LoxError::error(
+                    call.paren.span,
+                    "Can only call functions and classes.",
+                    ErrorType::RuntimeError,
+                ))
+            }
+        };
+
+        if !function.arity().accepts(arguments.len()) {
+            return Err(LoxError::error(
+                call.paren.span,
+                &format!(
+                    "Expected {} arguments but got {}.",
+                    function.arity(),
+                    arguments.len()
+                ),
+                ErrorType::RuntimeError,
+            ));
+        }
+
+        let span = call.callee.span().join(call.paren.span);
+        if self
+            .limits
+            .max_call_depth
+            .is_some_and(|max| self.call_depth >= max)
+        {
+            return Err(LoxError::limit_exceeded(span, Limit::CallDepth));
+        }
+
+        let previous = std::mem::replace(&mut self.call_span, span);
+        self.call_depth += 1;
+        let result = function.call(self, arguments);
+        self.call_depth -= 1;
+        self.call_span = previous;
+        result
+    }
+
+    fn visit_get_expr(&mut self, get: &GetExpr) -> Result<Object, LoxError> {
+        match self.evaluate(&get.object)? {
+            Object::Instance(instance) => {
+                LoxInstance::get(&instance, get.name.symbol, get.name.span, &mut self.heap)
+            }
+            Object::Foreign(object) => {
+                foreign::get(&object, get.name.symbol.as_str(), get.name.span)
+            }
+            _ => Err(LoxError::error(
+                get.name.span,
+                "Only instances have properties.",
+                ErrorType::RuntimeError,
+            )),
+        }
+    }
+
+    fn visit_set_expr(&mut self, set: &SetExpr) -> Result<Object, LoxError> {
+        let instance = match self.evaluate(&set.object)? {
+            Object::Instance(instance) => instance,
+            Object::Foreign(object) => {
+                let value = self.evaluate(&set.value)?;
+                foreign::set(
+                    &object,
+                    set.name.symbol.as_str(),
+                    value.clone(),
+                    set.name.span,
+                )?;
+                return Ok(value);
+            }
+            _ => {
+                return Err(LoxError::error(
+                    set.name.span,
+                    "Only instances have fields.",
+                    ErrorType::RuntimeError,
+                ))
+            }
+        };
+
+        let value = self.evaluate(&set.value)?;
+        self.allocate(std::mem::size_of::<Object>(), set.name.span)?;
+        instance.borrow_mut().set(set.name.symbol, value.clone());
+        Ok(value)
+    }
+
+    fn visit_super_expr(&mut self, sup: &SuperExpr) -> Result<Object, LoxError> {
+        let superclass = match self
+            .environment
+            .borrow()
+            .get(Symbol::SUPER, sup.keyword.span)?
+        {
+            Object::Class(class) => class,
+            _ => panic!("'super' should always be bound to a class"),
+        };
+        let instance = match self
+            .environment
+            .borrow()
+            .get(Symbol::THIS, sup.keyword.span)?
+        {
+            Object::Instance(instance) => instance,
+            _ => panic!("'this' should always be bound to an instance"),
+        };
+
+        match superclass.find_method(sup.method.symbol) {
+            Some(method) => Ok(Object::Function(method.bind(instance, &mut self.heap))),
+            None => Err(LoxError::error(
+                sup.method.span,
+                &format!("Undefined property '{}'.", sup.method.symbol),
+                ErrorType::RuntimeError,
+            )),
+        }
+    }
+
+    fn visit_this_expr(&mut self, this: &ThisExpr) -> Result<Object, LoxError> {
+        self.environment
+            .borrow()
+            .get(Symbol::THIS, this.keyword.span)
+    }
+
     fn visit_grouping_expr(&mut self, grouping: &GroupingExpr) -> Result<Object, LoxError> {
         self.evaluate(&grouping.expression)
     }
 
     fn visit_variable_expr(&mut self, expr: &VariableExpr) -> Result<Object, LoxError> {
-        // TODO: Shouldn't copy
-        self.environment.get(expr.name).cloned()
+        let name = expr.name;
+        match self.locals.get(&(&expr.name as *const Identifier)) {
+            Some(&distance) => self
+                .environment
+                .borrow()
+                .get_at(distance, name.symbol, name.span),
+            None => self.globals.borrow().get(name.symbol, name.span),
+        }
     }
 }
 
-impl StmtVisitor<()> for Interpreter<'_> {
-    fn visit_expr(&mut self, stmt: &ExpressionStmt) -> Result<(), LoxError> {
+impl StmtVisitor<ControlFlow> for Interpreter {
+    fn visit_block(&mut self, stmt: &BlockStmt) -> Result<ControlFlow, LoxError> {
+        let environment = Environment::with_enclosing(Rc::clone(&self.environment));
+        self.execute_block(&stmt.statements, environment)
+    }
+
+    fn visit_expr(&mut self, stmt: &ExpressionStmt) -> Result<ControlFlow, LoxError> {
         self.evaluate(&stmt.expression)?;
-        Ok(())
+        Ok(ControlFlow::Normal)
+    }
+
+    fn visit_class(&mut self, stmt: &ClassStmt) -> Result<ControlFlow, LoxError> {
+        let name = stmt.name.symbol;
+
+        let superclass = match &stmt.superclass {
+            Some(superclass) => match self.visit_variable_expr(superclass)? {
+                Object::Class(class) => Some(class),
+                _ => {
+                    return Err(LoxError::error(
+                        superclass.name.span,
+                        "Superclass must be a class.",
+                        ErrorType::RuntimeError,
+                    ))
+                }
+            },
+            None => None,
+        };
+
+        // methods of a subclass close over an extra scope holding `super`, so
+        // `super.method()` always resolves relative to the class it appears in
+        let closure = match &superclass {
+            Some(superclass) => {
+                let superclass = Object::Class(Rc::clone(superclass));
+                self.trace_define(Symbol::SUPER, &superclass, stmt.name.span);
+                let mut environment = Environment::with_enclosing(Rc::clone(&self.environment));
+                environment.define(Symbol::SUPER, superclass);
+                self.heap.manage(RefCell::new(environment))
+            }
+            None => Rc::clone(&self.environment),
+        };
+
+        let mut methods = HashMap::new();
+        for method in &stmt.methods {
+            let method_name = method.name.symbol;
+            let function = LoxFunction::new(
+                method_name,
+                Rc::clone(method),
+                Rc::clone(&closure),
+                method_name == Symbol::INIT,
+            );
+            methods.insert(method_name, self.heap.manage(function));
+        }
+
+        let class = LoxClass::new(name, superclass, methods);
+        self.allocate(std::mem::size_of::<LoxClass>(), stmt.name.span)?;
+        let class = self.heap.manage(class);
+        self.define(name, Object::Class(class), stmt.name.span);
+        Ok(ControlFlow::Normal)
     }
 
-    fn visit_print(&mut self, stmt: &PrintStmt) -> Result<(), LoxError> {
+    fn visit_function(&mut self, stmt: &Rc<FunctionStmt>) -> Result<ControlFlow, LoxError> {
+        let name = stmt.name.symbol;
+        let function = LoxFunction::new(name, Rc::clone(stmt), Rc::clone(&self.environment), false);
+        self.allocate(std::mem::size_of::<LoxFunction>(), stmt.name.span)?;
+
+        let function = self.heap.manage(function);
+        self.define(name, Object::Function(function), stmt.name.span);
+        Ok(ControlFlow::Normal)
+    }
+
+    fn visit_if(&mut self, stmt: &IfStmt) -> Result<ControlFlow, LoxError> {
+        let condition = self.evaluate(&stmt.condition)?;
+
+        if is_true(&condition, stmt.condition.span())? {
+            self.execute(&stmt.then_branch)
+        } else if let Some(else_branch) = &stmt.else_branch {
+            self.execute(else_branch)
+        } else {
+            Ok(ControlFlow::Normal)
+        }
+    }
+
+    fn visit_print(&mut self, stmt: &PrintStmt) -> Result<ControlFlow, LoxError> {
         let value = self.evaluate(&stmt.expression)?;
         println!("{}", value);
-        Ok(())
+        Ok(ControlFlow::Normal)
     }
 
-    fn visit_var(&mut self, stmt: &VarStmt) -> Result<(), LoxError> {
-        if stmt.initializer.is_some() {
-            let value = Some(self.evaluate(&stmt.initializer.as_ref().unwrap())?);
-            println!("{:?}", value);
-            self.environment
-                .define(stmt.name.as_string(&self.source), value);
-            Ok(())
-        } else {
-            return Err(LoxError::error(
-                stmt.name.span,
-                "Cannot use uninitialized value",
-                ErrorType::RuntimeError,
-            ));
+    fn visit_return(&mut self, stmt: &ReturnStmt) -> Result<ControlFlow, LoxError> {
+        let value = match &stmt.value {
+            Some(value) => self.evaluate(value)?,
+            None => Object::Nil,
+        };
+        Ok(ControlFlow::Return(value))
+    }
+
+    fn visit_var(&mut self, stmt: &VarStmt) -> Result<ControlFlow, LoxError> {
+        // uninitialized variables start out as nil
+        let value = match &stmt.initializer {
+            Some(initializer) => self.evaluate(initializer)?,
+            None => Object::Nil,
+        };
+        let name = stmt.name.symbol;
+        self.allocate(
+            name.as_str().len() + std::mem::size_of::<Object>(),
+            stmt.name.span,
+        )?;
+        self.define(name, value, stmt.name.span);
+        Ok(ControlFlow::Normal)
+    }
+
+    fn visit_while(&mut self, stmt: &WhileStmt) -> Result<ControlFlow, LoxError> {
+        while {
+            let condition = self.evaluate(&stmt.condition)?;
+            is_true(&condition, stmt.condition.span())?
+        } {
+            if let ControlFlow::Return(value) = self.execute(&stmt.body)? {
+                return Ok(ControlFlow::Return(value));
+            }
         }
+        Ok(ControlFlow::Normal)
+    }
+}
+
+/// Lox has no truthiness: only booleans can be used as conditions.
+fn is_true(value: &Object, span: Span) -> Result<bool, LoxError> {
+    match value {
+        Object::Bool(x) => Ok(*x),
+        _ => Err(LoxError::error(
+            span,
+            "Condition must be a boolean",
+            ErrorType::RuntimeError,
+        )),
     }
 }
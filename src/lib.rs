@@ -0,0 +1,125 @@
+//! A tree-walking interpreter for Lox.
+//!
+//! The simplest way to run code is through a [`Lox`] session:
+//!
+//! ```
+//! let mut lox = rlox::Lox::new();
+//! lox.eval("var a = 1;").unwrap();
+//! assert_eq!(lox.eval("a + 2;").unwrap(), rlox::Object::Num(3.0));
+//! ```
+//!
+//! The individual stages ([`Scanner`], [`Parser`] and [`Interpreter`]) can
+//! also be driven by hand.
+
+mod ast;
+mod chunk;
+mod class;
+mod compiler;
+mod environment;
+mod error;
+mod foreign;
+mod function;
+mod gc;
+mod interner;
+mod interpreter;
+mod limits;
+mod loxc;
+mod natives;
+mod parser;
+mod permissions;
+mod resolver;
+mod scanner;
+mod source_map;
+mod tokens;
+mod trace;
+mod vm;
+
+pub use ast::{Expr, Stmt};
+pub use error::{ErrorType, LoxError};
+pub use foreign::ForeignObject;
+pub use function::Arity;
+pub use gc::GcStats;
+pub use interner::{LoxString, Symbol};
+pub use interpreter::{Backend, Interpreter};
+pub use limits::{Limit, Limits};
+pub use loxc::{LoadError, LoadErrorKind, Program};
+pub use parser::Parser;
+pub use permissions::{Permission, Permissions};
+pub use scanner::Scanner;
+pub use source_map::SourceMap;
+pub use tokens::{KeywordType, Object, Span, Token, TokenType};
+pub use trace::{TraceFormat, Tracer};
+
+/// Scans `source` starting at byte `offset`, leaving out the whitespace and
+/// comments that the parser doesn't care about.
+pub fn tokenize(source: &str, offset: usize) -> Vec<Token> {
+    Scanner::with_offset(source, offset)
+        .filter(|x| !matches!(x.token_type, TokenType::Whitespace | TokenType::Comment))
+        .collect()
+}
+
+/// An interpreter session. Code evaluated in the same session shares its
+/// global variables, functions and classes.
+pub struct Lox {
+    interpreter: Interpreter,
+}
+
+impl Lox {
+    /// Creates a session whose scripts can't touch files, environment
+    /// variables or processes.
+    pub fn new() -> Self {
+        Self::with_permissions(Permissions::none())
+    }
+
+    pub fn with_permissions(permissions: Permissions) -> Self {
+        Self {
+            interpreter: Interpreter::new("", permissions),
+        }
+    }
+
+    /// Runs `source`. If the last statement is an expression statement its
+    /// value is returned, otherwise the result is `nil`.
+    ///
+    /// Syntax errors are all reported together; running stops at the first
+    /// runtime error.
+    pub fn eval(&mut self, source: &str) -> Result<Object, Vec<LoxError>> {
+        let offset = self.interpreter.append_source(source);
+        let source = self.interpreter.source();
+
+        let statements = Parser::new(source, tokenize(source, offset)).parse()?;
+        self.interpreter.start_timeout();
+        self.run(&statements).map_err(|error| vec![error])
+    }
+
+    fn run(&mut self, statements: &[Stmt]) -> Result<Object, LoxError> {
+        let mut value = Object::Nil;
+
+        for statement in statements {
+            value = match statement {
+                Stmt::Expression(stmt) => self.interpreter.run_expression(&stmt.expression)?,
+                _ => {
+                    self.interpreter
+                        .run_statements(std::slice::from_ref(statement))?;
+                    Object::Nil
+                }
+            };
+        }
+
+        Ok(value)
+    }
+
+    /// All the source code evaluated so far, which the spans in errors refer to.
+    pub fn source(&self) -> &str {
+        self.interpreter.source()
+    }
+
+    pub fn interpreter(&mut self) -> &mut Interpreter {
+        &mut self.interpreter
+    }
+}
+
+impl Default for Lox {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,74 @@
+use std::fmt;
+use std::time::Duration;
+
+/// How deep calls can nest by default. Each Lox call takes a few Rust stack
+/// frames, so without a limit deep recursion would crash the whole process.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
+/// How many statements and expressions the tree-walker lets nest inside each
+/// other for every call allowed by [`Limits::max_call_depth`]. A plain
+/// recursive call nests two, and every block, `if` or loop around it adds
+/// one more.
+pub const NESTING_PER_CALL: usize = 8;
+
+/// Resource limits for running untrusted scripts. `None` means unlimited.
+///
+/// ```
+/// use rlox::{ErrorType, Limit, Limits, Lox};
+///
+/// let mut lox = Lox::new();
+/// lox.interpreter().set_limits(Limits {
+///     max_steps: Some(10_000),
+///     ..Limits::default()
+/// });
+///
+/// let errors = lox.eval("while (true) {}").unwrap_err();
+/// assert_eq!(errors[0].error_type(), &ErrorType::LimitExceeded(Limit::Steps));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// How many statements and expressions can be evaluated.
+    pub max_steps: Option<u64>,
+    /// How deep function calls can nest. The tree-walker also stops when
+    /// statements and expressions nest more than eight times this deep, since
+    /// that uses up the Rust stack even if calls don't.
+    pub max_call_depth: Option<usize>,
+    /// Roughly how many bytes strings, environments, functions and instances
+    /// can take up. Everything allocated is counted, even if it has since been
+    /// freed, so this caps the total a script can allocate.
+    pub max_memory: Option<usize>,
+    /// How long each run, like one call to [`Lox::eval`](crate::Lox::eval),
+    /// can take.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_steps: None,
+            max_call_depth: Some(DEFAULT_MAX_CALL_DEPTH),
+            max_memory: None,
+            timeout: None,
+        }
+    }
+}
+
+/// Which of the [`Limits`] a script ran into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    Steps,
+    CallDepth,
+    Memory,
+    Time,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::Steps => write!(f, "Step limit exceeded."),
+            Limit::CallDepth => write!(f, "Stack overflow."),
+            Limit::Memory => write!(f, "Memory limit exceeded."),
+            Limit::Time => write!(f, "Time limit exceeded."),
+        }
+    }
+}
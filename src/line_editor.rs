@@ -0,0 +1,758 @@
+use std::io::{self, stdin, stdout, BufRead, IsTerminal, Read, Write};
+use std::path::PathBuf;
+
+/// Where history is kept, relative to the home directory.
+const HISTORY_FILE: &str = ".rlox_history";
+/// How many history entries are kept, in memory and in the history file.
+const MAX_HISTORY: usize = 1000;
+
+/// A minimal readline: cursor movement, history (with Ctrl-R search) and tab
+/// completion. When stdin or stdout isn't a terminal it falls back to plain
+/// buffered reads, so piping a script into the REPL still works.
+pub struct LineEditor {
+    history: Vec<String>,
+    history_path: Option<PathBuf>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Key {
+    Char(char),
+    Ctrl(char),
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    Unknown,
+}
+
+/// The line being edited, and where the cursor is in it.
+#[derive(Clone, Default)]
+struct Line {
+    chars: Vec<char>,
+    cursor: usize,
+}
+
+impl Line {
+    fn from(text: &str) -> Self {
+        let chars: Vec<char> = text.chars().collect();
+        Self {
+            cursor: chars.len(),
+            chars,
+        }
+    }
+
+    fn insert(&mut self, text: &str) {
+        for c in text.chars() {
+            self.chars.insert(self.cursor, c);
+            self.cursor += 1;
+        }
+    }
+
+    fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    /// The identifier-like word that ends at the cursor.
+    fn word_before_cursor(&self) -> String {
+        let start = self.chars[..self.cursor]
+            .iter()
+            .rposition(|&c| !is_word(c))
+            .map_or(0, |i| i + 1);
+        self.chars[start..self.cursor].iter().collect()
+    }
+}
+
+/// What happened to a line once editing stopped.
+enum Outcome {
+    Submit(String),
+    Eof,
+}
+
+impl LineEditor {
+    pub fn new() -> Self {
+        let history_path =
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE));
+
+        let mut history: Vec<String> = history_path
+            .as_ref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .map(|contents| contents.lines().map(str::to_string).collect())
+            .unwrap_or_default();
+        trim_history(&mut history);
+
+        Self {
+            history,
+            history_path,
+        }
+    }
+
+    /// Reads a line (without its line ending), or `None` once the input is
+    /// closed. `completions` are offered when Tab is pressed.
+    pub fn read_line(
+        &mut self,
+        prompt: &str,
+        completions: &[String],
+    ) -> io::Result<Option<String>> {
+        let raw_mode = if stdin().is_terminal() && stdout().is_terminal() {
+            sys::RawMode::enable().ok()
+        } else {
+            None
+        };
+
+        let Some(raw_mode) = raw_mode else {
+            return read_plain_line(prompt);
+        };
+
+        let outcome = self.edit(prompt, completions);
+        drop(raw_mode);
+
+        match outcome? {
+            Outcome::Submit(line) => {
+                // the cursor is still on the line that was just edited
+                println!();
+                Ok(Some(line))
+            }
+            Outcome::Eof => Ok(None),
+        }
+    }
+
+    /// Remembers `line` in memory and in the history file.
+    pub fn add_history(&mut self, line: &str) {
+        if line.trim().is_empty() || self.history.last().map(String::as_str) == Some(line) {
+            return;
+        }
+        self.history.push(line.to_string());
+        trim_history(&mut self.history);
+
+        if let Some(path) = &self.history_path {
+            // the file is rewritten rather than appended to so that it stays
+            // as short as the history in memory
+            let mut contents = self.history.join("\n");
+            contents.push('\n');
+            // not being able to save history shouldn't stop the REPL
+            let _ = std::fs::write(path, contents);
+        }
+    }
+
+    fn edit(&self, prompt: &str, completions: &[String]) -> io::Result<Outcome> {
+        let mut input = stdin().lock();
+        let mut out = stdout().lock();
+
+        let mut line = Line::default();
+        // `history.len()` stands for the new line that isn't in the history yet
+        let mut history_index = self.history.len();
+        let mut unsubmitted = Line::default();
+
+        refresh(&mut out, prompt, &line)?;
+        loop {
+            let Some(key) = read_key(&mut input)? else {
+                return Ok(Outcome::Eof);
+            };
+
+            match key {
+                Key::Enter => return Ok(Outcome::Submit(line.text())),
+                Key::Ctrl('d') if line.chars.is_empty() => return Ok(Outcome::Eof),
+                Key::Ctrl('c') => {
+                    // abandon the line, like a shell does
+                    write!(out, "^C\r\n")?;
+                    line = Line::default();
+                    history_index = self.history.len();
+                }
+                Key::Char(c) => line.insert(&c.to_string()),
+                Key::Tab => complete(&mut out, prompt, &mut line, completions)?,
+                Key::Backspace | Key::Ctrl('h') if line.cursor > 0 => {
+                    line.cursor -= 1;
+                    line.chars.remove(line.cursor);
+                }
+                Key::Delete | Key::Ctrl('d') if line.cursor < line.chars.len() => {
+                    line.chars.remove(line.cursor);
+                }
+                Key::Left | Key::Ctrl('b') => line.cursor = line.cursor.saturating_sub(1),
+                Key::Right | Key::Ctrl('f') => {
+                    line.cursor = (line.cursor + 1).min(line.chars.len())
+                }
+                Key::Home | Key::Ctrl('a') => line.cursor = 0,
+                Key::End | Key::Ctrl('e') => line.cursor = line.chars.len(),
+                Key::Ctrl('u') => {
+                    line.chars.drain(..line.cursor);
+                    line.cursor = 0;
+                }
+                Key::Ctrl('k') => {
+                    line.chars.truncate(line.cursor);
+                }
+                Key::Ctrl('w') => {
+                    let start = line.chars[..line.cursor]
+                        .iter()
+                        .rposition(|&c| !c.is_whitespace())
+                        .and_then(|end| line.chars[..end].iter().rposition(|&c| c.is_whitespace()))
+                        .map_or(0, |i| i + 1);
+                    line.chars.drain(start..line.cursor);
+                    line.cursor = start;
+                }
+                Key::Ctrl('l') => write!(out, "\x1b[H\x1b[2J")?,
+                Key::Up | Key::Ctrl('p') if history_index > 0 => {
+                    if history_index == self.history.len() {
+                        unsubmitted = line.clone();
+                    }
+                    history_index -= 1;
+                    line = Line::from(&self.history[history_index]);
+                }
+                Key::Down | Key::Ctrl('n') if history_index < self.history.len() => {
+                    history_index += 1;
+                    line = match self.history.get(history_index) {
+                        Some(entry) => Line::from(entry),
+                        None => unsubmitted.clone(),
+                    };
+                }
+                Key::Ctrl('r') => {
+                    if let Some((found, submit)) = self.reverse_search(&mut input, &mut out)? {
+                        line = Line::from(&found);
+                        if submit {
+                            refresh(&mut out, prompt, &line)?;
+                            return Ok(Outcome::Submit(found));
+                        }
+                    }
+                }
+                _ => {}
+            }
+
+            refresh(&mut out, prompt, &line)?;
+        }
+    }
+
+    /// Runs an incremental Ctrl-R search through the history. Returns the
+    /// entry that was picked, and whether it should be submitted right away,
+    /// or `None` if the search was cancelled.
+    fn reverse_search(
+        &self,
+        input: &mut impl Read,
+        out: &mut impl Write,
+    ) -> io::Result<Option<(String, bool)>> {
+        let mut query = String::new();
+        let mut found: Option<usize> = None;
+
+        loop {
+            let entry = found.map_or("", |i| self.history[i].as_str());
+            write!(out, "\r(reverse-i-search)'{query}': {entry}\x1b[K")?;
+            out.flush()?;
+
+            match read_key(input)? {
+                Some(Key::Char(c)) => {
+                    query.push(c);
+                    // the current match may well still match the longer query
+                    let before = found.map_or(self.history.len(), |i| i + 1);
+                    found = self.find(&query, before);
+                }
+                Some(Key::Backspace) => {
+                    query.pop();
+                    found = self.find(&query, self.history.len());
+                }
+                Some(Key::Ctrl('r')) => {
+                    let before = found.unwrap_or(self.history.len());
+                    found = self.find(&query, before).or(found);
+                }
+                Some(Key::Ctrl('g') | Key::Ctrl('c')) | None => return Ok(None),
+                Some(key) => {
+                    let entry = found.map(|i| self.history[i].clone()).unwrap_or_default();
+                    return Ok(Some((entry, key == Key::Enter)));
+                }
+            }
+        }
+    }
+
+    /// Finds the newest history entry before `before` that contains `query`.
+    fn find(&self, query: &str, before: usize) -> Option<usize> {
+        (0..before).rev().find(|&i| self.history[i].contains(query))
+    }
+}
+
+/// Forgets the oldest entries once there are more than [`MAX_HISTORY`].
+fn trim_history(history: &mut Vec<String>) {
+    if history.len() > MAX_HISTORY {
+        history.drain(..history.len() - MAX_HISTORY);
+    }
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Completes the word before the cursor. A unique match is filled in, and if
+/// there are several, as much as they have in common is filled in before
+/// listing them.
+fn complete(
+    out: &mut impl Write,
+    prompt: &str,
+    line: &mut Line,
+    completions: &[String],
+) -> io::Result<()> {
+    let prefix = line.word_before_cursor();
+    if prefix.is_empty() {
+        return Ok(());
+    }
+
+    let mut matches: Vec<&str> = completions
+        .iter()
+        .map(String::as_str)
+        .filter(|candidate| candidate.starts_with(&prefix))
+        .collect();
+    matches.sort_unstable();
+    matches.dedup();
+
+    let Some(first) = matches.first() else {
+        // nothing to complete, ring the bell
+        return write!(out, "\x07");
+    };
+
+    let common = matches.iter().fold(*first, |common, candidate| {
+        let len = common
+            .char_indices()
+            .zip(candidate.chars())
+            .take_while(|((_, a), b)| a == b)
+            .last()
+            .map_or(0, |((i, a), _)| i + a.len_utf8());
+        &common[..len]
+    });
+
+    if common.len() > prefix.len() {
+        line.insert(&common[prefix.len()..]);
+    } else if matches.len() > 1 {
+        write!(out, "\r\n{}\r\n", matches.join("  "))?;
+        refresh(out, prompt, line)?;
+    }
+    Ok(())
+}
+
+/// Redraws the prompt and line, and puts the cursor back where it belongs.
+fn refresh(out: &mut impl Write, prompt: &str, line: &Line) -> io::Result<()> {
+    write!(out, "\r{prompt}{}\x1b[K", line.text())?;
+
+    let back = line.chars.len() - line.cursor;
+    if back > 0 {
+        write!(out, "\x1b[{back}D")?;
+    }
+    out.flush()
+}
+
+fn read_byte(input: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    match input.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+/// Reads one key press, decoding escape sequences and UTF-8. Returns `None`
+/// at the end of the input.
+fn read_key(input: &mut impl Read) -> io::Result<Option<Key>> {
+    let Some(byte) = read_byte(input)? else {
+        return Ok(None);
+    };
+
+    let key = match byte {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Tab,
+        127 | 8 => Key::Backspace,
+        0x1b => read_escape(input)?,
+        1..=26 => Key::Ctrl((b'a' + byte - 1) as char),
+        0x20..=0x7e => Key::Char(byte as char),
+        0xc0.. => {
+            // the leading byte says how many continuation bytes follow
+            let length = match byte {
+                0xf0.. => 4,
+                0xe0.. => 3,
+                _ => 2,
+            };
+            let mut bytes = vec![byte; length];
+            input.read_exact(&mut bytes[1..])?;
+            match std::str::from_utf8(&bytes)
+                .ok()
+                .and_then(|s| s.chars().next())
+            {
+                Some(c) => Key::Char(c),
+                None => Key::Unknown,
+            }
+        }
+        _ => Key::Unknown,
+    };
+
+    Ok(Some(key))
+}
+
+/// Decodes the rest of an escape sequence, like `ESC [ A` for the up arrow.
+fn read_escape(input: &mut impl Read) -> io::Result<Key> {
+    let Some(b'[' | b'O') = read_byte(input)? else {
+        return Ok(Key::Unknown);
+    };
+
+    let key = match read_byte(input)? {
+        Some(b'A') => Key::Up,
+        Some(b'B') => Key::Down,
+        Some(b'C') => Key::Right,
+        Some(b'D') => Key::Left,
+        Some(b'H') => Key::Home,
+        Some(b'F') => Key::End,
+        // sequences like `ESC [ 3 ~`
+        Some(digit @ b'0'..=b'9') => {
+            let mut code = vec![digit];
+            while let Some(byte) = read_byte(input)? {
+                if byte == b'~' {
+                    break;
+                }
+                code.push(byte);
+            }
+            match code.as_slice() {
+                b"1" | b"7" => Key::Home,
+                b"4" | b"8" => Key::End,
+                b"3" => Key::Delete,
+                _ => Key::Unknown,
+            }
+        }
+        _ => Key::Unknown,
+    };
+
+    Ok(key)
+}
+
+fn read_plain_line(prompt: &str) -> io::Result<Option<String>> {
+    print!("{prompt}");
+    stdout().flush()?;
+
+    let mut line = String::new();
+    if stdin().lock().read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+
+    let len = line.trim_end_matches(['\n', '\r']).len();
+    line.truncate(len);
+    Ok(Some(line))
+}
+
+/// Switching the terminal in and out of raw mode. `termios` is laid out
+/// differently elsewhere, so other platforms fall back to plain line reads.
+#[cfg(all(
+    target_os = "linux",
+    target_env = "gnu",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+mod sys {
+    use std::io;
+
+    const NCCS: usize = 32;
+    const STDIN_FILENO: i32 = 0;
+    const TCSAFLUSH: i32 = 2;
+
+    // c_iflag
+    const ICRNL: u32 = 0o400;
+    const IXON: u32 = 0o2000;
+    // c_lflag
+    const ISIG: u32 = 0o1;
+    const ICANON: u32 = 0o2;
+    const ECHO: u32 = 0o10;
+    const IEXTEN: u32 = 0o100000;
+    // c_cc
+    const VTIME: usize = 5;
+    const VMIN: usize = 6;
+
+    /// `struct termios` as laid out by glibc on x86_64 and aarch64 Linux.
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct Termios {
+        c_iflag: u32,
+        c_oflag: u32,
+        c_cflag: u32,
+        c_lflag: u32,
+        c_line: u8,
+        c_cc: [u8; NCCS],
+        c_ispeed: u32,
+        c_ospeed: u32,
+    }
+
+    extern "C" {
+        fn tcgetattr(fd: i32, termios: *mut Termios) -> i32;
+        fn tcsetattr(fd: i32, optional_actions: i32, termios: *const Termios) -> i32;
+    }
+
+    /// Puts the terminal into raw mode, restoring the original settings when
+    /// dropped.
+    pub struct RawMode {
+        original: Termios,
+    }
+
+    impl RawMode {
+        pub fn enable() -> io::Result<Self> {
+            let mut original = Termios {
+                c_iflag: 0,
+                c_oflag: 0,
+                c_cflag: 0,
+                c_lflag: 0,
+                c_line: 0,
+                c_cc: [0; NCCS],
+                c_ispeed: 0,
+                c_ospeed: 0,
+            };
+            // SAFETY: `original` is a valid, writable `termios` struct
+            if unsafe { tcgetattr(STDIN_FILENO, &mut original) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let mut raw = original;
+            // output processing is left on so `\n` still starts a new line
+            raw.c_iflag &= !(ICRNL | IXON);
+            raw.c_lflag &= !(ECHO | ICANON | IEXTEN | ISIG);
+            raw.c_cc[VMIN] = 1;
+            raw.c_cc[VTIME] = 0;
+
+            // SAFETY: `raw` is a valid `termios` struct
+            if unsafe { tcsetattr(STDIN_FILENO, TCSAFLUSH, &raw) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(Self { original })
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            // SAFETY: `original` came from `tcgetattr`
+            unsafe {
+                tcsetattr(STDIN_FILENO, TCSAFLUSH, &self.original);
+            }
+        }
+    }
+}
+
+#[cfg(not(all(
+    target_os = "linux",
+    target_env = "gnu",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+)))]
+mod sys {
+    use std::io;
+
+    pub struct RawMode;
+
+    impl RawMode {
+        pub fn enable() -> io::Result<Self> {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "line editing isn't supported on this platform",
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads every key press in `bytes`.
+    fn keys(mut bytes: &[u8]) -> Vec<Key> {
+        let mut keys = vec![];
+        while let Some(key) = read_key(&mut bytes).unwrap() {
+            keys.push(key);
+        }
+        keys
+    }
+
+    fn editor(history: &[&str]) -> LineEditor {
+        LineEditor {
+            history: history.iter().map(|entry| entry.to_string()).collect(),
+            history_path: None,
+        }
+    }
+
+    /// Completes `text` with the cursor at its end, returning the new text and
+    /// what was written to the terminal.
+    fn complete_text(text: &str, completions: &[&str]) -> (String, String) {
+        let completions: Vec<String> = completions.iter().map(|c| c.to_string()).collect();
+        let mut line = Line::from(text);
+        let mut out = vec![];
+        complete(&mut out, "> ", &mut line, &completions).unwrap();
+        (line.text(), String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn plain_keys() {
+        assert_eq!(
+            keys(b"a~\r\n\t\x7f\x08\x01\x12"),
+            [
+                Key::Char('a'),
+                Key::Char('~'),
+                Key::Enter,
+                Key::Enter,
+                Key::Tab,
+                Key::Backspace,
+                Key::Backspace,
+                Key::Ctrl('a'),
+                Key::Ctrl('r'),
+            ]
+        );
+        assert_eq!(keys(b""), []);
+    }
+
+    #[test]
+    fn utf8_keys() {
+        assert_eq!(
+            keys("é→😀".as_bytes()),
+            [Key::Char('é'), Key::Char('→'), Key::Char('😀')]
+        );
+        assert_eq!(keys(b"\xc3\x28"), [Key::Unknown]);
+        // a stray continuation byte
+        assert_eq!(keys(b"\x80"), [Key::Unknown]);
+    }
+
+    #[test]
+    fn escape_sequences() {
+        assert_eq!(
+            keys(b"\x1b[A\x1b[B\x1b[C\x1b[D\x1b[H\x1b[F\x1bOH\x1bOF"),
+            [
+                Key::Up,
+                Key::Down,
+                Key::Right,
+                Key::Left,
+                Key::Home,
+                Key::End,
+                Key::Home,
+                Key::End,
+            ]
+        );
+        assert_eq!(
+            keys(b"\x1b[3~\x1b[1~\x1b[7~\x1b[4~\x1b[8~\x1b[5~\x1b[15~"),
+            [
+                Key::Delete,
+                Key::Home,
+                Key::Home,
+                Key::End,
+                Key::End,
+                Key::Unknown,
+                Key::Unknown,
+            ]
+        );
+        // an escape that isn't a sequence swallows the next byte
+        assert_eq!(keys(b"\x1bxa"), [Key::Unknown, Key::Char('a')]);
+        assert_eq!(keys(b"\x1b[Za"), [Key::Unknown, Key::Char('a')]);
+        assert_eq!(keys(b"\x1b"), [Key::Unknown]);
+    }
+
+    #[test]
+    fn word_before_cursor() {
+        assert_eq!(
+            Line::from("print foo_bar1").word_before_cursor(),
+            "foo_bar1"
+        );
+        assert_eq!(Line::from("a.b").word_before_cursor(), "b");
+        assert_eq!(Line::from("f(").word_before_cursor(), "");
+        assert_eq!(Line::from("").word_before_cursor(), "");
+
+        let mut line = Line::from("print value;");
+        line.cursor = 8;
+        assert_eq!(line.word_before_cursor(), "va");
+        line.cursor = 0;
+        assert_eq!(line.word_before_cursor(), "");
+    }
+
+    #[test]
+    fn completing_a_unique_match() {
+        let (text, out) = complete_text("print clo", &["clock", "class"]);
+        assert_eq!(text, "print clock");
+        assert_eq!(out, "");
+    }
+
+    #[test]
+    fn listing_several_matches() {
+        let (text, out) = complete_text("print cl", &["clock", "class", "var"]);
+        assert_eq!(text, "print cl");
+        assert!(out.starts_with("\r\nclass  clock\r\n"), "{out:?}");
+    }
+
+    #[test]
+    fn completing_what_matches_have_in_common() {
+        let (text, _) = complete_text("get", &["getName", "getNumber", "set"]);
+        assert_eq!(text, "getN");
+
+        // the common prefix ends on a character boundary
+        let (text, _) = complete_text("h", &["héllo", "hélp"]);
+        assert_eq!(text, "hél");
+
+        // duplicates count as one match
+        let (text, _) = complete_text("x", &["xs", "xs"]);
+        assert_eq!(text, "xs");
+    }
+
+    #[test]
+    fn completing_nothing() {
+        let (text, out) = complete_text("zz", &["clock"]);
+        assert_eq!(text, "zz");
+        assert_eq!(out, "\x07");
+
+        let (text, out) = complete_text("print ", &["clock"]);
+        assert_eq!(text, "print ");
+        assert_eq!(out, "");
+    }
+
+    #[test]
+    fn finding_history() {
+        let editor = editor(&["print 1;", "var a;", "print 2;"]);
+        assert_eq!(editor.find("print", 3), Some(2));
+        assert_eq!(editor.find("print", 2), Some(0));
+        assert_eq!(editor.find("print", 0), None);
+        assert_eq!(editor.find("missing", 3), None);
+        assert_eq!(editor.find("", 3), Some(2));
+    }
+
+    #[test]
+    fn reverse_search() {
+        let editor = editor(&["print 1;", "var a;", "print 2;"]);
+        let search = |mut input: &[u8]| {
+            let mut out = vec![];
+            editor.reverse_search(&mut input, &mut out).unwrap()
+        };
+
+        assert_eq!(search(b"pr\r"), Some(("print 2;".to_string(), true)));
+        // Ctrl-R again finds older matches
+        assert_eq!(search(b"pr\x12\r"), Some(("print 1;".to_string(), true)));
+        assert_eq!(
+            search(b"pr\x12\x12\r"),
+            Some(("print 1;".to_string(), true))
+        );
+        // other keys pick the match for editing
+        assert_eq!(search(b"var\x1b[C"), Some(("var a;".to_string(), false)));
+        assert_eq!(search(b"vx\x7f\r"), Some(("var a;".to_string(), true)));
+        assert_eq!(search(b"pr\x07"), None);
+        assert_eq!(search(b"pr"), None);
+    }
+
+    #[test]
+    fn adding_history() {
+        let mut editor = editor(&[]);
+        editor.add_history("a");
+        editor.add_history("a");
+        editor.add_history("  ");
+        editor.add_history("b");
+        editor.add_history("a");
+        assert_eq!(editor.history, ["a", "b", "a"]);
+    }
+
+    #[test]
+    fn trimming_history() {
+        let mut editor = editor(&[]);
+        for i in 0..MAX_HISTORY + 5 {
+            editor.add_history(&i.to_string());
+        }
+        assert_eq!(editor.history.len(), MAX_HISTORY);
+        assert_eq!(editor.history[0], "5");
+
+        let mut history = vec![String::new(); MAX_HISTORY + 1];
+        history[1] = "oldest kept".to_string();
+        trim_history(&mut history);
+        assert_eq!(history.len(), MAX_HISTORY);
+        assert_eq!(history[0], "oldest kept");
+    }
+}
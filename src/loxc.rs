@@ -0,0 +1,764 @@
+//! The `.loxc` format: a parsed program saved to disk, so that it can be run
+//! without scanning and parsing it again.
+//!
+//! A file starts with [`MAGIC`] and a little endian `u16` format version,
+//! followed by the source code and the syntax tree. Tokens in the tree are
+//! stored as spans into the source, which is kept so that names can be looked
+//! up and errors can point at lines. Their types are worked out again from
+//! their text when the file is loaded, which also checks that they are the
+//! kind of token that belongs there.
+//!
+//! Numbers (lengths, offsets and tags) are LEB128 encoded, number literals
+//! are stored as little endian `f64`s.
+//!
+//! ```
+//! use rlox::{tokenize, LoadErrorKind, Parser, Program};
+//!
+//! let source = "print 1 + 2;";
+//! let statements = Parser::new(source, tokenize(source, 0)).parse().unwrap();
+//! let bytes = Program::encode(source, &statements);
+//! assert!(Program::is_loxc(&bytes));
+//!
+//! let program = Program::decode(&bytes).unwrap();
+//! assert_eq!(program.source, source);
+//! assert_eq!(program.statements.len(), 1);
+//!
+//! let error = Program::decode(&bytes[..bytes.len() - 1]).unwrap_err();
+//! assert_eq!(error.kind, LoadErrorKind::Truncated);
+//! ```
+
+use crate::ast::*;
+use crate::interner::Symbol;
+use crate::tokens::{KeywordType, Object, Span, Token, TokenType};
+use std::fmt;
+use std::rc::Rc;
+
+/// The first bytes of every `.loxc` file.
+pub const MAGIC: &[u8; 4] = b"LOXC";
+
+/// The version of the format written by [`Program::encode`]. Files with any other
+/// version are rejected.
+pub const FORMAT_VERSION: u16 = 1;
+
+/// Nodes can't be nested deeper than this, so that a malicious file can't
+/// overflow the stack while it is being loaded.
+const MAX_DEPTH: usize = 2048;
+
+/// A program loaded from a `.loxc` file.
+#[derive(Debug)]
+pub struct Program {
+    /// The source code the program was parsed from, which the spans in
+    /// `statements` point into.
+    pub source: String,
+    pub statements: Vec<Stmt>,
+}
+
+/// Why a `.loxc` file couldn't be loaded.
+#[derive(Debug, PartialEq, Eq)]
+pub struct LoadError {
+    /// Where in the file the problem was found.
+    pub offset: usize,
+    pub kind: LoadErrorKind,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum LoadErrorKind {
+    /// The file doesn't start with the `LOXC` magic bytes.
+    NotLoxc,
+    UnsupportedVersion(u16),
+    /// The file ends in the middle of something.
+    Truncated,
+    /// A node, literal or option has a tag that doesn't mean anything.
+    InvalidTag {
+        what: &'static str,
+        tag: u64,
+    },
+    InvalidNumber,
+    InvalidUtf8,
+    /// A span reaches outside the source, or splits a character in two.
+    InvalidSpan,
+    /// A token doesn't fit where it is used, like a `+` as a variable name.
+    UnexpectedToken {
+        expected: &'static str,
+    },
+    TooDeep,
+    /// There is more data after the end of the program.
+    TrailingData,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            LoadErrorKind::NotLoxc => return write!(f, "not a .loxc file"),
+            LoadErrorKind::UnsupportedVersion(version) => {
+                return write!(
+                    f,
+                    "unsupported .loxc format version {version}, expected {FORMAT_VERSION}"
+                )
+            }
+            LoadErrorKind::Truncated => write!(f, "file is truncated")?,
+            LoadErrorKind::InvalidTag { what, tag } => write!(f, "invalid {what} tag {tag}")?,
+            LoadErrorKind::InvalidNumber => write!(f, "invalid number")?,
+            LoadErrorKind::InvalidUtf8 => write!(f, "invalid UTF-8 in string")?,
+            LoadErrorKind::InvalidSpan => write!(f, "span is outside of the source")?,
+            LoadErrorKind::UnexpectedToken { expected } => write!(f, "expected {expected} token")?,
+            LoadErrorKind::TooDeep => write!(f, "program is nested too deeply")?,
+            LoadErrorKind::TrailingData => write!(f, "unexpected data after the program")?,
+        }
+        write!(f, " at byte {}", self.offset)
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl Program {
+    /// Whether `bytes` start like a `.loxc` file, rather than source code.
+    pub fn is_loxc(bytes: &[u8]) -> bool {
+        bytes.starts_with(MAGIC)
+    }
+
+    /// Serializes a parsed program along with the source it was parsed from.
+    pub fn encode(source: &str, statements: &[Stmt]) -> Vec<u8> {
+        let mut encoder = Encoder { bytes: Vec::new() };
+        encoder.bytes.extend_from_slice(MAGIC);
+        encoder
+            .bytes
+            .extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        encoder.string(source);
+        encoder.statements(statements);
+        encoder.bytes
+    }
+
+    /// Loads a program serialized by [`Program::encode`].
+    pub fn decode(bytes: &[u8]) -> Result<Program, LoadError> {
+        decode(bytes)
+    }
+}
+
+fn decode(bytes: &[u8]) -> Result<Program, LoadError> {
+    if !bytes.starts_with(MAGIC) {
+        return Err(LoadError {
+            offset: 0,
+            kind: LoadErrorKind::NotLoxc,
+        });
+    }
+
+    let mut decoder = Decoder {
+        bytes,
+        offset: MAGIC.len(),
+        source: String::new(),
+        depth: 0,
+    };
+    let version = u16::from_le_bytes(decoder.array()?);
+    if version != FORMAT_VERSION {
+        return Err(decoder.error(LoadErrorKind::UnsupportedVersion(version)));
+    }
+
+    decoder.source = decoder.string()?;
+    let statements = decoder.statements()?;
+    if decoder.offset != bytes.len() {
+        return Err(decoder.error(LoadErrorKind::TrailingData));
+    }
+
+    Ok(Program {
+        source: decoder.source,
+        statements,
+    })
+}
+
+struct Encoder {
+    bytes: Vec<u8>,
+}
+
+impl Encoder {
+    fn number(&mut self, mut n: u64) {
+        loop {
+            let byte = (n & 0x7f) as u8;
+            n >>= 7;
+            if n == 0 {
+                self.bytes.push(byte);
+                return;
+            }
+            self.bytes.push(byte | 0x80);
+        }
+    }
+
+    fn usize(&mut self, n: usize) {
+        self.number(n as u64);
+    }
+
+    fn string(&mut self, text: &str) {
+        self.usize(text.len());
+        self.bytes.extend_from_slice(text.as_bytes());
+    }
+
+    fn span(&mut self, span: Span) {
+        self.usize(span.start);
+        self.usize(span.end - span.start);
+    }
+
+    fn token(&mut self, token: Token) {
+        self.span(token.span);
+    }
+
+    fn identifier(&mut self, identifier: Identifier) {
+        self.span(identifier.span);
+    }
+
+    fn literal(&mut self, value: &Object) {
+        match value {
+            Object::Nil => self.number(0),
+            Object::Bool(false) => self.number(1),
+            Object::Bool(true) => self.number(2),
+            Object::Num(n) => {
+                self.number(3);
+                self.bytes.extend_from_slice(&n.to_le_bytes());
+            }
+            Object::Str(s) => {
+                self.number(4);
+                self.string(s);
+            }
+            _ => panic!("literals are only ever nil, booleans, numbers and strings"),
+        }
+    }
+
+    fn statements(&mut self, statements: &[Stmt]) {
+        self.usize(statements.len());
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Block(v) => {
+                self.number(0);
+                self.statements(&v.statements);
+            }
+            Stmt::Class(v) => {
+                self.number(1);
+                self.identifier(v.name);
+                match &v.superclass {
+                    Some(superclass) => {
+                        self.number(1);
+                        self.identifier(superclass.name);
+                    }
+                    None => self.number(0),
+                }
+                self.usize(v.methods.len());
+                for method in &v.methods {
+                    self.function(method);
+                }
+            }
+            Stmt::Expression(v) => {
+                self.number(2);
+                self.expression(&v.expression);
+            }
+            Stmt::Function(v) => {
+                self.number(3);
+                self.function(v);
+            }
+            Stmt::If(v) => {
+                self.number(4);
+                self.token(v.keyword);
+                self.expression(&v.condition);
+                self.statement(&v.then_branch);
+                match &v.else_branch {
+                    Some(else_branch) => {
+                        self.number(1);
+                        self.statement(else_branch);
+                    }
+                    None => self.number(0),
+                }
+            }
+            Stmt::Print(v) => {
+                self.number(5);
+                self.expression(&v.expression);
+            }
+            Stmt::Return(v) => {
+                self.number(6);
+                self.token(v.keyword);
+                self.optional_expression(v.value.as_ref());
+            }
+            Stmt::Var(v) => {
+                self.number(7);
+                self.identifier(v.name);
+                self.optional_expression(v.initializer.as_ref());
+            }
+            Stmt::While(v) => {
+                self.number(8);
+                self.token(v.keyword);
+                self.expression(&v.condition);
+                self.statement(&v.body);
+            }
+        }
+    }
+
+    fn function(&mut self, function: &FunctionStmt) {
+        self.identifier(function.name);
+        self.usize(function.params.len());
+        for param in &function.params {
+            self.identifier(*param);
+        }
+        self.statements(&function.body);
+    }
+
+    fn optional_expression(&mut self, expr: Option<&Expr>) {
+        match expr {
+            Some(expr) => {
+                self.number(1);
+                self.expression(expr);
+            }
+            None => self.number(0),
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assign(v) => {
+                self.number(0);
+                self.identifier(v.name);
+                self.expression(&v.value);
+            }
+            Expr::Binary(v) => {
+                self.number(1);
+                self.expression(&v.left);
+                self.token(v.operator);
+                self.expression(&v.right);
+            }
+            Expr::Call(v) => {
+                self.number(2);
+                self.expression(&v.callee);
+                self.token(v.paren);
+                self.usize(v.arguments.len());
+                for argument in &v.arguments {
+                    self.expression(argument);
+                }
+            }
+            Expr::Get(v) => {
+                self.number(3);
+                self.expression(&v.object);
+                self.identifier(v.name);
+            }
+            Expr::Grouping(v) => {
+                self.number(4);
+                self.expression(&v.expression);
+            }
+            Expr::Literal(v) => {
+                self.number(5);
+                self.literal(&v.value);
+                self.span(v.span);
+            }
+            Expr::Logical(v) => {
+                self.number(6);
+                self.expression(&v.left);
+                self.token(v.operator);
+                self.expression(&v.right);
+            }
+            Expr::Set(v) => {
+                self.number(7);
+                self.expression(&v.object);
+                self.identifier(v.name);
+                self.expression(&v.value);
+            }
+            Expr::Super(v) => {
+                self.number(8);
+                self.token(v.keyword);
+                self.identifier(v.method);
+            }
+            Expr::This(v) => {
+                self.number(9);
+                self.token(v.keyword);
+            }
+            Expr::Unary(v) => {
+                self.number(10);
+                self.token(v.operator);
+                self.expression(&v.right);
+            }
+            Expr::Variable(v) => {
+                self.number(11);
+                self.identifier(v.name);
+            }
+        }
+    }
+}
+
+const BINARY_OPERATORS: &[TokenType] = &[
+    TokenType::Plus,
+    TokenType::Minus,
+    TokenType::Star,
+    TokenType::Slash,
+    TokenType::Greater,
+    TokenType::GreaterEqual,
+    TokenType::Less,
+    TokenType::LessEqual,
+    TokenType::EqualEqual,
+    TokenType::BangEqual,
+];
+const LOGICAL_OPERATORS: &[TokenType] = &[
+    TokenType::Keyword(KeywordType::And),
+    TokenType::Keyword(KeywordType::Or),
+];
+const UNARY_OPERATORS: &[TokenType] = &[TokenType::Minus, TokenType::Bang];
+const LOOP_KEYWORDS: &[TokenType] = &[
+    TokenType::Keyword(KeywordType::While),
+    // `for` loops are turned into `while` loops by the parser
+    TokenType::Keyword(KeywordType::For),
+];
+
+struct Decoder<'bytes> {
+    bytes: &'bytes [u8],
+    offset: usize,
+    source: String,
+    /// How deeply nested the node being decoded is.
+    depth: usize,
+}
+
+impl Decoder<'_> {
+    fn error(&self, kind: LoadErrorKind) -> LoadError {
+        LoadError {
+            offset: self.offset,
+            kind,
+        }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&[u8], LoadError> {
+        let end = self
+            .offset
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| self.error(LoadErrorKind::Truncated))?;
+        let bytes = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], LoadError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+
+    fn number(&mut self) -> Result<u64, LoadError> {
+        let start = self.offset;
+        let mut n = 0u64;
+        for shift in (0..64).step_by(7) {
+            let [byte] = self.array()?;
+            let bits = u64::from(byte & 0x7f);
+            if shift == 63 && bits > 1 {
+                break;
+            }
+            n |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err(LoadError {
+            offset: start,
+            kind: LoadErrorKind::InvalidNumber,
+        })
+    }
+
+    fn usize(&mut self) -> Result<usize, LoadError> {
+        let start = self.offset;
+        let n = self.number()?;
+        usize::try_from(n).map_err(|_| LoadError {
+            offset: start,
+            kind: LoadErrorKind::InvalidNumber,
+        })
+    }
+
+    /// Reads a tag and checks that it is below `count`.
+    fn tag(&mut self, what: &'static str, count: u64) -> Result<u64, LoadError> {
+        let start = self.offset;
+        let tag = self.number()?;
+        if tag >= count {
+            return Err(LoadError {
+                offset: start,
+                kind: LoadErrorKind::InvalidTag { what, tag },
+            });
+        }
+        Ok(tag)
+    }
+
+    /// Reads a length, which can't be more than the bytes that are left since
+    /// every item takes at least one byte. This keeps a corrupt length from
+    /// allocating huge vectors.
+    fn length(&mut self) -> Result<usize, LoadError> {
+        let len = self.usize()?;
+        if len > self.bytes.len() - self.offset {
+            return Err(self.error(LoadErrorKind::Truncated));
+        }
+        Ok(len)
+    }
+
+    fn string(&mut self) -> Result<String, LoadError> {
+        let len = self.length()?;
+        let start = self.offset;
+        let bytes = self.bytes(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| LoadError {
+            offset: start,
+            kind: LoadErrorKind::InvalidUtf8,
+        })
+    }
+
+    fn span(&mut self) -> Result<Span, LoadError> {
+        let offset = self.offset;
+        let start = self.usize()?;
+        let len = self.usize()?;
+
+        let span = start
+            .checked_add(len)
+            .filter(|&end| self.source.get(start..end).is_some())
+            .map(|end| Span::from(start..end));
+        span.ok_or(LoadError {
+            offset,
+            kind: LoadErrorKind::InvalidSpan,
+        })
+    }
+
+    /// Reads a token, which must be one of `allowed`.
+    fn token(&mut self, allowed: &[TokenType], expected: &'static str) -> Result<Token, LoadError> {
+        let offset = self.offset;
+        let span = self.span()?;
+
+        match token_type(&self.source[span.start..span.end]) {
+            Some(token_type) if allowed.contains(&token_type) => Ok(Token { token_type, span }),
+            _ => Err(LoadError {
+                offset,
+                kind: LoadErrorKind::UnexpectedToken { expected },
+            }),
+        }
+    }
+
+    fn identifier(&mut self) -> Result<Identifier, LoadError> {
+        let token = self.token(&[TokenType::Identifier], "an identifier")?;
+        Ok(Identifier {
+            symbol: Symbol::intern(&self.source[token.span.start..token.span.end]),
+            span: token.span,
+        })
+    }
+
+    fn keyword(&mut self, keyword: KeywordType) -> Result<Token, LoadError> {
+        self.token(&[TokenType::Keyword(keyword)], keyword.lexeme())
+    }
+
+    fn literal(&mut self) -> Result<Object, LoadError> {
+        Ok(match self.tag("literal", 5)? {
+            0 => Object::Nil,
+            1 => Object::Bool(false),
+            2 => Object::Bool(true),
+            3 => Object::Num(f64::from_le_bytes(self.array()?)),
+            _ => Object::Str(self.string()?.into()),
+        })
+    }
+
+    fn option(&mut self) -> Result<bool, LoadError> {
+        Ok(self.tag("option", 2)? == 1)
+    }
+
+    /// Runs `decode` one level deeper into the tree.
+    fn nested<T>(
+        &mut self,
+        decode: impl FnOnce(&mut Self) -> Result<T, LoadError>,
+    ) -> Result<T, LoadError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(self.error(LoadErrorKind::TooDeep));
+        }
+        self.depth += 1;
+        let result = decode(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn statements(&mut self) -> Result<Vec<Stmt>, LoadError> {
+        let len = self.length()?;
+        let mut statements = Vec::with_capacity(len);
+        for _ in 0..len {
+            statements.push(self.statement()?);
+        }
+        Ok(statements)
+    }
+
+    fn statement(&mut self) -> Result<Stmt, LoadError> {
+        self.nested(|decoder| {
+            Ok(match decoder.tag("statement", 9)? {
+                0 => Stmt::Block(BlockStmt {
+                    statements: decoder.statements()?,
+                }),
+                1 => {
+                    let name = decoder.identifier()?;
+                    let superclass = match decoder.option()? {
+                        true => Some(VariableExpr {
+                            name: decoder.identifier()?,
+                        }),
+                        false => None,
+                    };
+                    let len = decoder.length()?;
+                    let mut methods = Vec::with_capacity(len);
+                    for _ in 0..len {
+                        methods.push(Rc::new(decoder.function()?));
+                    }
+                    Stmt::Class(ClassStmt {
+                        name,
+                        superclass,
+                        methods,
+                    })
+                }
+                2 => Stmt::Expression(ExpressionStmt {
+                    expression: decoder.expression()?,
+                }),
+                3 => Stmt::Function(Rc::new(decoder.function()?)),
+                4 => Stmt::If(IfStmt {
+                    keyword: decoder.keyword(KeywordType::If)?,
+                    condition: decoder.expression()?,
+                    then_branch: Box::new(decoder.statement()?),
+                    else_branch: match decoder.option()? {
+                        true => Some(Box::new(decoder.statement()?)),
+                        false => None,
+                    },
+                }),
+                5 => Stmt::Print(PrintStmt {
+                    expression: decoder.expression()?,
+                }),
+                6 => Stmt::Return(ReturnStmt {
+                    keyword: decoder.keyword(KeywordType::Return)?,
+                    value: decoder.optional_expression()?,
+                }),
+                7 => Stmt::Var(VarStmt {
+                    name: decoder.identifier()?,
+                    initializer: decoder.optional_expression()?,
+                }),
+                _ => Stmt::While(WhileStmt {
+                    keyword: decoder.token(LOOP_KEYWORDS, "a loop keyword")?,
+                    condition: decoder.expression()?,
+                    body: Box::new(decoder.statement()?),
+                }),
+            })
+        })
+    }
+
+    fn function(&mut self) -> Result<FunctionStmt, LoadError> {
+        let name = self.identifier()?;
+        let len = self.length()?;
+        let mut params = Vec::with_capacity(len);
+        for _ in 0..len {
+            params.push(self.identifier()?);
+        }
+        let body = self.statements()?;
+        Ok(FunctionStmt { name, params, body })
+    }
+
+    fn optional_expression(&mut self) -> Result<Option<Expr>, LoadError> {
+        match self.option()? {
+            true => Ok(Some(self.expression()?)),
+            false => Ok(None),
+        }
+    }
+
+    fn boxed_expression(&mut self) -> Result<Box<Expr>, LoadError> {
+        Ok(Box::new(self.expression()?))
+    }
+
+    fn expression(&mut self) -> Result<Expr, LoadError> {
+        self.nested(|decoder| {
+            Ok(match decoder.tag("expression", 12)? {
+                0 => Expr::Assign(AssignExpr {
+                    name: decoder.identifier()?,
+                    value: decoder.boxed_expression()?,
+                }),
+                1 => Expr::Binary(BinaryExpr {
+                    left: decoder.boxed_expression()?,
+                    operator: decoder.token(BINARY_OPERATORS, "a binary operator")?,
+                    right: decoder.boxed_expression()?,
+                }),
+                2 => {
+                    let callee = decoder.boxed_expression()?;
+                    let paren = decoder.token(&[TokenType::RightParen], "')'")?;
+                    let len = decoder.length()?;
+                    let mut arguments = Vec::with_capacity(len);
+                    for _ in 0..len {
+                        arguments.push(decoder.expression()?);
+                    }
+                    Expr::Call(CallExpr {
+                        callee,
+                        paren,
+                        arguments,
+                    })
+                }
+                3 => Expr::Get(GetExpr {
+                    object: decoder.boxed_expression()?,
+                    name: decoder.identifier()?,
+                }),
+                4 => Expr::Grouping(GroupingExpr {
+                    expression: decoder.boxed_expression()?,
+                }),
+                5 => Expr::Literal(LiteralExpr {
+                    value: decoder.literal()?,
+                    span: decoder.span()?,
+                }),
+                6 => Expr::Logical(LogicalExpr {
+                    left: decoder.boxed_expression()?,
+                    operator: decoder.token(LOGICAL_OPERATORS, "a logical operator")?,
+                    right: decoder.boxed_expression()?,
+                }),
+                7 => Expr::Set(SetExpr {
+                    object: decoder.boxed_expression()?,
+                    name: decoder.identifier()?,
+                    value: decoder.boxed_expression()?,
+                }),
+                8 => Expr::Super(SuperExpr {
+                    keyword: decoder.keyword(KeywordType::Super)?,
+                    method: decoder.identifier()?,
+                }),
+                9 => Expr::This(ThisExpr {
+                    keyword: decoder.keyword(KeywordType::This)?,
+                }),
+                10 => Expr::Unary(UnaryExpr {
+                    operator: decoder.token(UNARY_OPERATORS, "a unary operator")?,
+                    right: decoder.boxed_expression()?,
+                }),
+                _ => Expr::Variable(VariableExpr {
+                    name: decoder.identifier()?,
+                }),
+            })
+        })
+    }
+}
+
+/// Works out the type of a token from its text, for the kinds of tokens
+/// that appear in syntax trees.
+fn token_type(lexeme: &str) -> Option<TokenType> {
+    let token_type = match lexeme {
+        ")" => TokenType::RightParen,
+        "-" => TokenType::Minus,
+        "+" => TokenType::Plus,
+        "/" => TokenType::Slash,
+        "*" => TokenType::Star,
+        "!" => TokenType::Bang,
+        "!=" => TokenType::BangEqual,
+        "==" => TokenType::EqualEqual,
+        ">" => TokenType::Greater,
+        ">=" => TokenType::GreaterEqual,
+        "<" => TokenType::Less,
+        "<=" => TokenType::LessEqual,
+        _ => {
+            if let Some(keyword) = KeywordType::ALL
+                .into_iter()
+                .find(|keyword| keyword.lexeme() == lexeme)
+            {
+                return Some(TokenType::Keyword(keyword));
+            }
+
+            let mut chars = lexeme.chars();
+            let starts_like_identifier = chars
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+            if !starts_like_identifier || !chars.all(|c| c.is_alphanumeric() || c == '_') {
+                return None;
+            }
+            TokenType::Identifier
+        }
+    };
+    Some(token_type)
+}
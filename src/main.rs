@@ -1,90 +1,165 @@
-mod ast;
+use rlox::{tokenize, ErrorType, Interpreter, LoxError, Parser, Program, SourceMap, Stmt};
 
-mod error;
-use error::LoxError;
+mod cli;
+use cli::{Command, Options};
 
-mod parser;
-use parser::Parser;
+mod line_editor;
 
-mod scanner;
-use crate::scanner::Scanner;
-
-mod tokens;
-use tokens::{Span, Token, TokenType};
-
-mod interpreter;
-use interpreter::Interpreter;
-
-mod environment;
+mod repl;
+use repl::Repl;
 
 use std::env;
-use std::io::{self, stdin, stdout, Write};
-
-fn run_file(path: &str) -> io::Result<()> {
-    let file_content = std::fs::read_to_string(path)?;
-    execute(&file_content);
+use std::io;
+use std::path::Path;
+
+fn run_file(path: &str, options: &Options) -> io::Result<()> {
+    let file_content = std::fs::read(path)?;
+
+    // errors are reported (and the process exited) inside `execute`
+    if Program::is_loxc(&file_content) {
+        let program = match Program::decode(&file_content) {
+            Ok(program) => program,
+            Err(e) => {
+                if !options.quiet {
+                    eprintln!("rlox: couldn't load '{path}': {e}");
+                }
+                std::process::exit(65);
+            }
+        };
+        let _ = execute(path, &program.source, &program.statements, options);
+    } else {
+        let source = String::from_utf8(file_content)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let ast = parse_or_exit(path, &source, options);
+        let _ = execute(path, &source, &ast, options);
+    }
     Ok(())
 }
 
-// the result is useless for now but will be useful eventually
-fn execute(source: &str) -> Result<(), LoxError> {
-    let scanner = Scanner::new(source).filter(|x| x.token_type != TokenType::Whitespace);
+/// Parses a script and saves it as a `.loxc` file.
+fn compile_file(path: &str, options: &Options) -> io::Result<()> {
+    let source = std::fs::read_to_string(path)?;
+    let ast = parse_or_exit(path, &source, options);
+
+    let output = match &options.output {
+        Some(output) => output.clone(),
+        None => Path::new(path)
+            .with_extension("loxc")
+            .to_string_lossy()
+            .into_owned(),
+    };
+    std::fs::write(output, Program::encode(&source, &ast))
+}
+
+/// Scans and parses `source` starting at byte `offset`, dumping the tokens
+/// and syntax tree along the way if the options ask for it.
+fn parse(source: &str, offset: usize, options: &Options) -> Result<Vec<Stmt>, Vec<LoxError>> {
     //TODO: use the iterator instead of collecting
-    let mut tokens: Vec<Token> = scanner.collect();
+    let tokens = tokenize(source, offset);
 
-    for token in &tokens {
-        println!("{token:?}");
+    if options.dump_tokens {
+        for token in &tokens {
+            eprintln!("{token:?}");
+        }
     }
 
-    let mut parser = Parser::new(source, tokens);
-    let ast = &parser.parse();
+    let ast = Parser::new(source, tokens).parse();
 
-    match ast {
-        Ok(x) => {
-            println!("{:#?}", x)
+    if let Ok(ref x) = ast {
+        if options.dump_ast {
+            eprintln!("{:#?}", x);
         }
-        Err(e) => {
-            e.report();
+    }
+
+    ast
+}
+
+/// Parses a whole file, reporting any errors and exiting if there are some.
+fn parse_or_exit(name: &str, source: &str, options: &Options) -> Vec<Stmt> {
+    match parse(source, 0, options) {
+        Ok(ast) => ast,
+        Err(errors) => {
+            if !options.quiet {
+                let source_map = SourceMap::new(name, source);
+                for e in errors {
+                    e.report(&source_map);
+                }
+            }
             std::process::exit(65);
         }
     }
+}
 
-    let mut interpreter = Interpreter::new(source);
-    let expr = interpreter.interpret(ast.as_ref().unwrap());
+// the result is useless for now but will be useful eventually
+fn execute(name: &str, source: &str, ast: &[Stmt], options: &Options) -> Result<(), LoxError> {
+    let source_map = SourceMap::new(name, source);
+
+    let mut interpreter = Interpreter::new(source, options.permissions.clone());
+    interpreter.set_tracer(options.tracer());
+    interpreter.set_gc_stress(options.gc_stress);
+    interpreter
+        .set_backend(options.backend)
+        .expect("the backend is chosen before anything runs");
+    let expr = interpreter.interpret(ast);
+
+    if options.gc_stats {
+        eprintln!("gc: {}", interpreter.gc_stats());
+    }
 
-    match expr {
-        Ok(ref x) => { /*println!("{:#?}", x)*/ }
-        Err(ref e) => {
-            e.report();
-            std::process::exit(70);
+    if let Err(ref e) = expr {
+        if !options.quiet {
+            e.report(&source_map);
         }
+        // the bytecode backend finds some mistakes while compiling, before
+        // anything has run
+        let code = if e.error_type() == &ErrorType::ParseError {
+            65
+        } else {
+            70
+        };
+        std::process::exit(code);
     }
 
     Ok(())
 }
 
-fn run_prompt() {
-    println!("Rlox");
-    loop {
-        let mut line_input: String = String::new();
-        print!("> ");
-        stdout().flush().expect("Failed to flush");
-        stdin()
-            .read_line(&mut line_input)
-            .expect("Failed to read line");
-
-        execute(&line_input);
+/// The stack scripts run on. The tree-walker recurses once for every nested
+/// statement or expression, and a debug build takes a couple of kilobytes of
+/// stack each time, so the default call depth limit needs far more than the
+/// main thread's 8MB.
+const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+fn main() {
+    let interpreter = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run)
+        .expect("Failed to start the interpreter thread");
+
+    // a panic has already been reported by the thread
+    if interpreter.join().is_err() {
+        std::process::exit(101);
     }
 }
 
-fn main() {
-    let cli_args: Vec<String> = env::args().collect();
+fn run() {
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("rlox: {message}\n\n{}", cli::USAGE);
+            std::process::exit(64);
+        }
+    };
 
-    let args_length = cli_args.len() - 1;
+    if options.help {
+        println!("{}", cli::USAGE);
+        return;
+    }
 
-    match args_length {
-        n if n > 2 => println!("Usage: rlox [script] optionally: --verbose"),
-        1 => run_file(&cli_args[1]).expect("Failed to run file"),
-        _ => run_prompt(),
+    match (options.command, &options.script) {
+        (Command::Compile, Some(path)) => {
+            compile_file(path, &options).expect("Failed to compile file")
+        }
+        (_, Some(path)) => run_file(path, &options).expect("Failed to run file"),
+        (_, None) => Repl::new(&options).run().expect("Failed to read line"),
     }
 }
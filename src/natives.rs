@@ -0,0 +1,105 @@
+use crate::error::LoxError;
+use crate::function::Arity;
+use crate::interner::LoxString;
+use crate::interpreter::Interpreter;
+use crate::tokens::Object;
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Registers the built-in functions every script can use. The ones that
+/// touch the outside world check the interpreter's permissions first.
+pub fn define_globals(interpreter: &mut Interpreter) {
+    interpreter.define_native(
+        "clock",
+        Arity::Exactly(0),
+        |interpreter, _| match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(time) => Ok(Object::Num(time.as_secs_f64())),
+            Err(_) => Err(interpreter.runtime_error("The system clock is set before 1970.")),
+        },
+    );
+
+    interpreter.define_native("readFile", Arity::Exactly(1), |interpreter, arguments| {
+        let path = string_argument(interpreter, "readFile", &arguments[0])?;
+        let Some(resolved) = interpreter.permissions().readable(Path::new(path)) else {
+            return Err(denied(interpreter, &format!("reading '{path}'"), "fs:read"));
+        };
+
+        std::fs::read_to_string(resolved)
+            .map(|contents| Object::Str(contents.into()))
+            .map_err(|e| interpreter.runtime_error(&format!("Couldn't read '{path}': {e}.")))
+    });
+
+    interpreter.define_native("writeFile", Arity::Exactly(2), |interpreter, arguments| {
+        let path = string_argument(interpreter, "writeFile", &arguments[0])?;
+        let contents = string_argument(interpreter, "writeFile", &arguments[1])?;
+        let Some(resolved) = interpreter.permissions().writable(Path::new(path)) else {
+            return Err(denied(
+                interpreter,
+                &format!("writing '{path}'"),
+                "fs:write",
+            ));
+        };
+
+        std::fs::write(resolved, contents)
+            .map(|_| Object::Nil)
+            .map_err(|e| interpreter.runtime_error(&format!("Couldn't write '{path}': {e}.")))
+    });
+
+    interpreter.define_native("getEnv", Arity::Exactly(1), |interpreter, arguments| {
+        let name = string_argument(interpreter, "getEnv", &arguments[0])?;
+        if !interpreter.permissions().can_read_env() {
+            return Err(denied(interpreter, &format!("reading ${name}"), "env"));
+        }
+
+        // unset (or non-unicode) variables are nil
+        Ok(std::env::var(name).map_or(Object::Nil, |value| Object::Str(value.into())))
+    });
+
+    interpreter.define_native("exec", Arity::AtLeast(1), |interpreter, arguments| {
+        let mut words = Vec::with_capacity(arguments.len());
+        for argument in arguments {
+            words.push(string_argument(interpreter, "exec", argument)?);
+        }
+        if !interpreter.permissions().can_run_processes() {
+            return Err(denied(
+                interpreter,
+                &format!("running '{}'", words[0]),
+                "process",
+            ));
+        }
+
+        // returns what the program printed, like a shell's $(...)
+        let output = Command::new(words[0])
+            .args(&words[1..])
+            .output()
+            .map_err(|e| {
+                interpreter.runtime_error(&format!("Couldn't run '{}': {e}.", words[0]))
+            })?;
+        if !output.status.success() {
+            return Err(
+                interpreter.runtime_error(&format!("'{}' failed: {}.", words[0], output.status))
+            );
+        }
+        Ok(Object::Str(LoxString::new(&String::from_utf8_lossy(
+            &output.stdout,
+        ))))
+    });
+}
+
+fn string_argument<'a>(
+    interpreter: &Interpreter,
+    function: &str,
+    argument: &'a Object,
+) -> Result<&'a str, LoxError> {
+    match argument {
+        Object::Str(s) => Ok(s),
+        _ => Err(interpreter.runtime_error(&format!("{function}() only takes strings."))),
+    }
+}
+
+fn denied(interpreter: &Interpreter, action: &str, permission: &str) -> LoxError {
+    interpreter.runtime_error(&format!(
+        "Permission denied: {action} needs the '{permission}' permission."
+    ))
+}
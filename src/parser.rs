@@ -1,53 +1,344 @@
-use crate::ast::{Stmt::Var, *};
+use crate::ast::{Identifier, *};
 use crate::error::{ErrorType::ParseError, LoxError};
-use crate::scanner::Scanner;
+use crate::interner::{LoxString, Symbol};
 use crate::tokens::{TokenType::*, *};
-use std::iter::Peekable;
+use std::rc::Rc;
+
+/// Functions can't take more arguments than this.
+const MAX_ARGUMENTS: usize = 255;
+
+/// The kind of function body currently being parsed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum FunctionKind {
+    None,
+    Function,
+    Method,
+    Initializer,
+}
+
+/// The kind of class body currently being parsed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum ClassKind {
+    None,
+    Class,
+    Subclass,
+}
+
+impl FunctionKind {
+    fn name(self) -> &'static str {
+        match self {
+            FunctionKind::None | FunctionKind::Function => "function",
+            FunctionKind::Method | FunctionKind::Initializer => "method",
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Parser<'source> {
     tokens: Vec<Token>,
     source: &'source str,
     current: usize,
+    /// Used to reject `return`s outside of functions and in initializers.
+    current_function: FunctionKind,
+    /// Used to reject `this` outside of methods and `super` outside of subclasses.
+    current_class: ClassKind,
+    /// Errors that leave the parser in a state it can carry on from, like a
+    /// `return` in the wrong place, so they don't need to unwind.
+    errors: Vec<LoxError>,
 }
 
 impl<'source> Parser<'source> {
-    pub fn new(source: &str, tokens: Vec<Token>) -> Parser {
+    pub fn new(source: &'source str, mut tokens: Vec<Token>) -> Self {
+        // the scanner doesn't emit an end of file token, but having one saves
+        // every lookahead from having to deal with running out of tokens
+        tokens.push(Token {
+            token_type: Eof,
+            span: Span::from(source.len()..source.len()),
+        });
+
         Parser {
             tokens,
             source,
             current: 0,
+            current_function: FunctionKind::None,
+            current_class: ClassKind::None,
+            errors: vec![],
         }
     }
 
-    pub fn parse(&mut self) -> Result<Vec<Stmt>, LoxError> {
-        // this will be expanded on when statements are added
+    /// Parses the whole program. Parsing carries on after a syntax error so
+    /// that every error in the source is reported, not just the first one.
+    pub fn parse(&mut self) -> Result<Vec<Stmt>, Vec<LoxError>> {
         let mut statements = vec![];
+
         while !self.is_at_end() {
-            statements.push(self.statement()?);
+            if let Some(statement) = self.declaration() {
+                statements.push(statement);
+            }
         }
 
-        Ok(statements)
-        /*
-        match self.expression()? {
-            expr => Ok(expr),
-            _ => Err(LoxError::error(
-                self.tokens[self.current].span,
-                "Need either statement or expression (how did you even get here?)",
+        let mut errors = std::mem::take(&mut self.errors);
+        errors.sort_by_key(|error| error.span().start);
+        if errors.is_empty() {
+            Ok(statements)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Parses a single expression that has to make up all of the input.
+    pub fn parse_expression(&mut self) -> Result<Expr, LoxError> {
+        let expr = self.expression()?;
+
+        if let Some(error) = self.errors.first() {
+            return Err(error.clone());
+        }
+        if !self.is_at_end() {
+            return Err(LoxError::error(
+                self.peek().span,
+                "Expect end of expression.",
                 ParseError,
-            )),
+            ));
+        }
+        Ok(expr)
+    }
+
+    /// Parses a declaration, or records the error and skips to the next
+    /// statement if it is malformed. Recovering here rather than at the top
+    /// level means a mistake inside a block doesn't take the rest of the
+    /// block, and the function around it, down with it.
+    fn declaration(&mut self) -> Option<Stmt> {
+        match self.try_declaration() {
+            Ok(statement) => Some(statement),
+            Err(error) => {
+                self.errors.push(error);
+                self.synchronize();
+                None
+            }
+        }
+    }
+
+    fn try_declaration(&mut self) -> Result<Stmt, LoxError> {
+        if self.is_match(&[Keyword(KeywordType::Class)]).is_some() {
+            return self.class_declaration();
+        }
+
+        if self.is_match(&[Keyword(KeywordType::Fun)]).is_some() {
+            return Ok(Stmt::Function(self.function(FunctionKind::Function)?));
+        }
+
+        if self.is_match(&[Keyword(KeywordType::Var)]).is_some() {
+            return self.var_declaration();
+        }
+
+        self.statement()
+    }
+
+    fn class_declaration(&mut self) -> Result<Stmt, LoxError> {
+        let name = self.identifier("Expect class name.")?;
+
+        let superclass = match self.is_match(&[Less]) {
+            Some(_) => {
+                let superclass = self.identifier("Expect superclass name.")?;
+                if superclass.symbol == name.symbol {
+                    self.error(superclass.span, "A class can't inherit from itself.");
+                }
+                Some(VariableExpr { name: superclass })
+            }
+            None => None,
+        };
+
+        self.consume(LeftBrace, "Expect '{' before class body.")?;
+
+        let kind = if superclass.is_some() {
+            ClassKind::Subclass
+        } else {
+            ClassKind::Class
+        };
+        let enclosing_class = std::mem::replace(&mut self.current_class, kind);
+        let methods = self.class_body();
+        self.current_class = enclosing_class;
+
+        Ok(Stmt::Class(ClassStmt {
+            name,
+            superclass,
+            methods: methods?,
+        }))
+    }
+
+    fn class_body(&mut self) -> Result<Vec<Rc<FunctionStmt>>, LoxError> {
+        let mut methods = vec![];
+
+        while !self.check(RightBrace) && !self.is_at_end() {
+            let span = self.peek().span;
+            let kind = if &self.source[span.start..span.end] == "init" {
+                FunctionKind::Initializer
+            } else {
+                FunctionKind::Method
+            };
+            methods.push(self.function(kind)?);
+        }
+
+        self.consume(RightBrace, "Expect '}' after class body.")?;
+        Ok(methods)
+    }
+
+    /// Parses the name, parameters and body of a function.
+    fn function(&mut self, kind: FunctionKind) -> Result<Rc<FunctionStmt>, LoxError> {
+        let kind_name = kind.name();
+        let name = self.identifier(&format!("Expect {kind_name} name."))?;
+        self.consume(LeftParen, &format!("Expect '(' after {kind_name} name."))?;
+
+        let mut params = vec![];
+        if !self.check(RightParen) {
+            loop {
+                if params.len() == MAX_ARGUMENTS {
+                    self.error(self.peek().span, "Can't have more than 255 parameters.");
+                }
+                params.push(self.identifier("Expect parameter name.")?);
+
+                if self.is_match(&[Comma]).is_none() {
+                    break;
+                }
+            }
         }
-        */
+        self.consume(RightParen, "Expect ')' after parameters.")?;
+
+        self.consume(LeftBrace, &format!("Expect '{{' before {kind_name} body."))?;
+        let enclosing_function = std::mem::replace(&mut self.current_function, kind);
+        let body = self.block();
+        self.current_function = enclosing_function;
+
+        Ok(Rc::new(FunctionStmt {
+            name,
+            params,
+            body: body?,
+        }))
+    }
+
+    fn var_declaration(&mut self) -> Result<Stmt, LoxError> {
+        let name = self.identifier("Expect variable name.")?;
+
+        let initializer = match self.is_match(&[Equal]) {
+            Some(_) => Some(self.expression()?),
+            None => None,
+        };
+
+        self.consume(Semicolon, "Expect ';' after variable declaration.")?;
+        Ok(Stmt::Var(VarStmt { name, initializer }))
     }
 
     fn statement(&mut self) -> Result<Stmt, LoxError> {
-        if let Some(token) = self.is_match(&[Keyword(KeywordType::Print)]) {
+        if let Some(keyword) = self.is_match(&[Keyword(KeywordType::For)]) {
+            return self.for_statement(keyword);
+        }
+
+        if let Some(keyword) = self.is_match(&[Keyword(KeywordType::If)]) {
+            return self.if_statement(keyword);
+        }
+
+        if self.is_match(&[Keyword(KeywordType::Print)]).is_some() {
             return self.print_statement();
         }
 
+        if let Some(keyword) = self.is_match(&[Keyword(KeywordType::Return)]) {
+            return self.return_statement(keyword);
+        }
+
+        if let Some(keyword) = self.is_match(&[Keyword(KeywordType::While)]) {
+            return self.while_statement(keyword);
+        }
+
+        if self.is_match(&[LeftBrace]).is_some() {
+            return Ok(Stmt::Block(BlockStmt {
+                statements: self.block()?,
+            }));
+        }
+
         self.expression_statement()
     }
 
+    /// Parses a `for` loop and desugars it into a `while` loop:
+    ///
+    /// `for (init; cond; incr) body` becomes `{ init; while (cond) { body; incr; } }`
+    fn for_statement(&mut self, keyword: Token) -> Result<Stmt, LoxError> {
+        self.consume(LeftParen, "Expect '(' after 'for'.")?;
+
+        let initializer = if self.is_match(&[Semicolon]).is_some() {
+            None
+        } else if self.is_match(&[Keyword(KeywordType::Var)]).is_some() {
+            Some(self.var_declaration()?)
+        } else {
+            Some(self.expression_statement()?)
+        };
+
+        let condition = if !self.check(Semicolon) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        self.consume(Semicolon, "Expect ';' after loop condition.")?;
+
+        let increment = if !self.check(RightParen) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        self.consume(RightParen, "Expect ')' after for clauses.")?;
+
+        let mut body = self.statement()?;
+
+        if let Some(increment) = increment {
+            body = Stmt::Block(BlockStmt {
+                statements: vec![
+                    body,
+                    Stmt::Expression(ExpressionStmt {
+                        expression: increment,
+                    }),
+                ],
+            });
+        }
+
+        // an omitted condition loops forever
+        let condition = condition.unwrap_or(Expr::Literal(LiteralExpr {
+            value: Object::Bool(true),
+            span: keyword.span,
+        }));
+        body = Stmt::While(WhileStmt {
+            keyword,
+            condition,
+            body: Box::new(body),
+        });
+
+        if let Some(initializer) = initializer {
+            body = Stmt::Block(BlockStmt {
+                statements: vec![initializer, body],
+            });
+        }
+
+        Ok(body)
+    }
+
+    fn if_statement(&mut self, keyword: Token) -> Result<Stmt, LoxError> {
+        self.consume(LeftParen, "Expect '(' after 'if'.")?;
+        let condition = self.expression()?;
+        self.consume(RightParen, "Expect ')' after if condition.")?;
+
+        let then_branch = Box::new(self.statement()?);
+        // eagerly grabbing the `else` binds it to the innermost `if`
+        let else_branch = match self.is_match(&[Keyword(KeywordType::Else)]) {
+            Some(_) => Some(Box::new(self.statement()?)),
+            None => None,
+        };
+
+        Ok(Stmt::If(IfStmt {
+            keyword,
+            condition,
+            then_branch,
+            else_branch,
+        }))
+    }
+
     fn print_statement(&mut self) -> Result<Stmt, LoxError> {
         let value = self.expression()?;
 
@@ -55,6 +346,50 @@ impl<'source> Parser<'source> {
         Ok(Stmt::Print(PrintStmt { expression: value }))
     }
 
+    fn return_statement(&mut self, keyword: Token) -> Result<Stmt, LoxError> {
+        if self.current_function == FunctionKind::None {
+            self.error(keyword.span, "Can't return from top-level code.");
+        }
+
+        let value = if !self.check(Semicolon) {
+            if self.current_function == FunctionKind::Initializer {
+                self.error(keyword.span, "Can't return a value from an initializer.");
+            }
+            Some(self.expression()?)
+        } else {
+            None
+        };
+
+        self.consume(Semicolon, "Expect ';' after return value.")?;
+        Ok(Stmt::Return(ReturnStmt { keyword, value }))
+    }
+
+    fn while_statement(&mut self, keyword: Token) -> Result<Stmt, LoxError> {
+        self.consume(LeftParen, "Expect '(' after 'while'.")?;
+        let condition = self.expression()?;
+        self.consume(RightParen, "Expect ')' after condition.")?;
+        let body = Box::new(self.statement()?);
+
+        Ok(Stmt::While(WhileStmt {
+            keyword,
+            condition,
+            body,
+        }))
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, LoxError> {
+        let mut statements = vec![];
+
+        while !self.check(RightBrace) && !self.is_at_end() {
+            if let Some(statement) = self.declaration() {
+                statements.push(statement);
+            }
+        }
+
+        self.consume(RightBrace, "Expect '}' after block.")?;
+        Ok(statements)
+    }
+
     fn expression_statement(&mut self) -> Result<Stmt, LoxError> {
         let expr = self.expression()?;
         self.consume(Semicolon, "Expect ';' after expression")?;
@@ -62,14 +397,74 @@ impl<'source> Parser<'source> {
     }
 
     fn expression(&mut self) -> Result<Expr, LoxError> {
-        self.equality()
+        self.assignment()
+    }
+
+    fn assignment(&mut self) -> Result<Expr, LoxError> {
+        let start = self.peek().span.start;
+        let expr = self.or()?;
+
+        if self.is_match(&[Equal]).is_some() {
+            // the target has already been parsed as an ordinary expression, so
+            // this is where we find out whether it can actually be assigned to
+            let target = Span::from(start..self.tokens[self.current - 2].span.end);
+            // assignment is right-associative, so recurse instead of looping
+            let value = self.assignment()?;
+
+            return match expr {
+                Expr::Variable(variable) => Ok(Expr::Assign(AssignExpr {
+                    name: variable.name,
+                    value: Box::new(value),
+                })),
+                Expr::Get(get) => Ok(Expr::Set(SetExpr {
+                    object: get.object,
+                    name: get.name,
+                    value: Box::new(value),
+                })),
+                _ => {
+                    self.error(target, "Invalid assignment target.");
+                    Ok(expr)
+                }
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn or(&mut self) -> Result<Expr, LoxError> {
+        let mut expr = self.and()?;
+
+        while let Some(operator) = self.is_match(&[Keyword(KeywordType::Or)]) {
+            let right = self.and()?;
+            expr = Expr::Logical(LogicalExpr {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            });
+        }
+
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, LoxError> {
+        let mut expr = self.equality()?;
+
+        while let Some(operator) = self.is_match(&[Keyword(KeywordType::And)]) {
+            let right = self.equality()?;
+            expr = Expr::Logical(LogicalExpr {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            });
+        }
+
+        Ok(expr)
     }
 
     fn equality(&mut self) -> Result<Expr, LoxError> {
         let mut expr = self.comparison()?;
 
-        if let Some(operator) = self.is_match(&[BangEqual, Equal]) {
-            println!("{}", operator);
+        while let Some(operator) = self.is_match(&[BangEqual, EqualEqual]) {
             let right = self.comparison()?;
             expr = Ok(Expr::Binary(BinaryExpr {
                 left: Box::new(expr),
@@ -84,10 +479,8 @@ impl<'source> Parser<'source> {
     fn comparison(&mut self) -> Result<Expr, LoxError> {
         let mut expr = self.term()?;
 
-        if let Some(operator) = self.is_match(&[Greater, GreaterEqual, Less, LessEqual, EqualEqual])
-        {
+        while let Some(operator) = self.is_match(&[Greater, GreaterEqual, Less, LessEqual]) {
             let right = self.term()?;
-            println!("{}", operator);
             expr = Ok(Expr::Binary(BinaryExpr {
                 left: Box::new(expr),
                 operator,
@@ -101,8 +494,7 @@ impl<'source> Parser<'source> {
     fn term(&mut self) -> Result<Expr, LoxError> {
         let mut expr = self.factor()?;
 
-        if let Some(operator) = self.is_match(&[Plus, Minus]) {
-            println!("{}", operator);
+        while let Some(operator) = self.is_match(&[Plus, Minus]) {
             let right = self.factor()?;
             expr = Ok(Expr::Binary(BinaryExpr {
                 left: Box::new(expr),
@@ -117,8 +509,7 @@ impl<'source> Parser<'source> {
     fn factor(&mut self) -> Result<Expr, LoxError> {
         let mut expr = self.unary()?;
 
-        if let Some(operator) = self.is_match(&[Slash, Star]) {
-            println!("{}", operator);
+        while let Some(operator) = self.is_match(&[Slash, Star]) {
             let right = self.unary()?;
             expr = Ok(Expr::Binary(BinaryExpr {
                 left: Box::new(expr),
@@ -132,7 +523,6 @@ impl<'source> Parser<'source> {
 
     fn unary(&mut self) -> Result<Expr, LoxError> {
         if let Some(operator) = self.is_match(&[Bang, Minus]) {
-            println!("{}", operator);
             let right = self.unary()?;
             return Ok(Expr::Unary(UnaryExpr {
                 operator,
@@ -140,7 +530,52 @@ impl<'source> Parser<'source> {
             }));
         }
 
-        self.primary()
+        self.call()
+    }
+
+    fn call(&mut self) -> Result<Expr, LoxError> {
+        let mut expr = self.primary()?;
+
+        loop {
+            if self.is_match(&[LeftParen]).is_some() {
+                expr = self.finish_call(expr)?;
+            } else if self.is_match(&[Dot]).is_some() {
+                let name = self.identifier("Expect property name after '.'.")?;
+                expr = Expr::Get(GetExpr {
+                    object: Box::new(expr),
+                    name,
+                });
+            } else {
+                break;
+            }
+        }
+
+        Ok(expr)
+    }
+
+    fn finish_call(&mut self, callee: Expr) -> Result<Expr, LoxError> {
+        let mut arguments = vec![];
+
+        if !self.check(RightParen) {
+            loop {
+                if arguments.len() == MAX_ARGUMENTS {
+                    self.error(self.peek().span, "Can't have more than 255 arguments.");
+                }
+                arguments.push(self.expression()?);
+
+                if self.is_match(&[Comma]).is_none() {
+                    break;
+                }
+            }
+        }
+
+        let paren = self.consume(RightParen, "Expect ')' after arguments.")?;
+
+        Ok(Expr::Call(CallExpr {
+            callee: Box::new(callee),
+            paren,
+            arguments,
+        }))
     }
 
     fn primary(&mut self) -> Result<Expr, LoxError> {
@@ -154,8 +589,35 @@ impl<'source> Parser<'source> {
             Keyword(KeywordType::True) => Object::Bool(true),
             Keyword(KeywordType::Nil) => Object::Nil,
             // cut out the quotes
-            StringLiteral => Object::Str(self.source[start + 1..end - 1].to_string()),
+            StringLiteral => Object::Str(LoxString::new(&self.source[start + 1..end - 1])),
             Number => Object::Num(self.source[start..end].parse::<f64>().unwrap()),
+            TokenType::Identifier => {
+                let name = self.identifier("Expect expression.")?;
+                return Ok(Expr::Variable(VariableExpr { name }));
+            }
+            Keyword(KeywordType::This) => {
+                let keyword = self.advance();
+                if self.current_class == ClassKind::None {
+                    self.error(keyword.span, "Can't use 'this' outside of a class.");
+                }
+                return Ok(Expr::This(ThisExpr { keyword }));
+            }
+            Keyword(KeywordType::Super) => {
+                let keyword = self.advance();
+                match self.current_class {
+                    ClassKind::None => {
+                        self.error(keyword.span, "Can't use 'super' outside of a class.")
+                    }
+                    ClassKind::Class => self.error(
+                        keyword.span,
+                        "Can't use 'super' in a class with no superclass.",
+                    ),
+                    ClassKind::Subclass => {}
+                }
+                self.consume(Dot, "Expect '.' after 'super'.")?;
+                let method = self.identifier("Expect superclass method name.")?;
+                return Ok(Expr::Super(SuperExpr { keyword, method }));
+            }
             LeftParen => {
                 self.advance();
                 let expr = self.expression()?;
@@ -164,9 +626,24 @@ impl<'source> Parser<'source> {
                     expression: Box::new(expr),
                 }));
             }
+            // the scanner hands back unterminated strings as unknown tokens
+            Unknown if self.source[start..end].starts_with('"') => {
+                return Err(LoxError::error(
+                    self.peek().span,
+                    "Unterminated string.",
+                    ParseError,
+                ));
+            }
+            Unknown => {
+                return Err(LoxError::error(
+                    self.peek().span,
+                    "Unexpected character.",
+                    ParseError,
+                ));
+            }
             _ => {
                 return Err(LoxError::error(
-                    self.tokens[self.current].span,
+                    self.peek().span,
                     "Expected expression",
                     ParseError,
                 ));
@@ -175,7 +652,10 @@ impl<'source> Parser<'source> {
         if !self.is_at_end() {
             self.advance();
         }
-        Ok(Expr::Literal(LiteralExpr { value }))
+        Ok(Expr::Literal(LiteralExpr {
+            value,
+            span: Span::from(start..end),
+        }))
     }
 
     fn consume(&mut self, token_type: TokenType, message: &str) -> Result<Token, LoxError> {
@@ -190,11 +670,19 @@ impl<'source> Parser<'source> {
         }
     }
 
+    /// Consumes an identifier and interns its name.
+    fn identifier(&mut self, message: &str) -> Result<Identifier, LoxError> {
+        let token = self.consume(TokenType::Identifier, message)?;
+        Ok(Identifier {
+            symbol: Symbol::intern(&self.source[token.span.start..token.span.end]),
+            span: token.span,
+        })
+    }
+
     fn is_match(&mut self, token_types: &[TokenType]) -> Option<Token> {
         for &t in token_types {
             if self.check(t) {
-                self.advance();
-                return Some(self.peek());
+                return Some(self.advance());
             }
         }
 
@@ -225,22 +713,40 @@ impl<'source> Parser<'source> {
     }
 
     fn is_at_end(&self) -> bool {
-        self.tokens.get(self.current).is_none()
+        self.peek().token_type == Eof
     }
 
+    /// Records an error without unwinding, for mistakes that don't leave the
+    /// parser confused about where it is.
+    fn error(&mut self, span: Span, message: &str) {
+        self.errors.push(LoxError::error(span, message, ParseError));
+    }
+
+    /// Discards tokens until we are (probably) at the start of the next
+    /// statement, so one syntax error doesn't cause a cascade of bogus ones.
     fn synchronize(&mut self) {
         self.advance();
 
         while !self.is_at_end() {
-            if self.peek().token_type == Semicolon {
+            if self.previous().token_type == Semicolon {
                 return;
             }
 
-            if self.peek().token_type == Keyword(KeywordType::Return) {
-                return;
+            match self.peek().token_type {
+                Keyword(
+                    KeywordType::Class
+                    | KeywordType::Fun
+                    | KeywordType::Var
+                    | KeywordType::For
+                    | KeywordType::If
+                    | KeywordType::While
+                    | KeywordType::Print
+                    | KeywordType::Return,
+                ) => return,
+                _ => {
+                    self.advance();
+                }
             }
-
-            self.advance();
         }
     }
 }
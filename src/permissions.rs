@@ -0,0 +1,185 @@
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+/// Something a script can be allowed to do outside of the interpreter.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Permission {
+    /// Read files, anywhere or only inside the given directory.
+    Read(Option<PathBuf>),
+    /// Write files, anywhere or only inside the given directory.
+    Write(Option<PathBuf>),
+    /// Read environment variables.
+    Env,
+    /// Run other programs.
+    Process,
+}
+
+impl FromStr for Permission {
+    type Err = String;
+
+    /// Parses entries like `fs:read=/data`, `fs:write`, `env` or `process`.
+    fn from_str(entry: &str) -> Result<Self, Self::Err> {
+        let (kind, path) = match entry.split_once('=') {
+            Some((kind, path)) => (kind, Some(PathBuf::from(path))),
+            None => (entry, None),
+        };
+
+        match (kind, path) {
+            ("fs:read", path) => Ok(Permission::Read(path)),
+            ("fs:write", path) => Ok(Permission::Write(path)),
+            ("env", None) => Ok(Permission::Env),
+            ("process", None) => Ok(Permission::Process),
+            ("env" | "process", Some(_)) => Err(format!("'{kind}' doesn't take a path")),
+            _ => Err(format!("unknown permission '{entry}'")),
+        }
+    }
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Permission::Read(None) => write!(f, "fs:read"),
+            Permission::Read(Some(path)) => write!(f, "fs:read={}", path.display()),
+            Permission::Write(None) => write!(f, "fs:write"),
+            Permission::Write(Some(path)) => write!(f, "fs:write={}", path.display()),
+            Permission::Env => write!(f, "env"),
+            Permission::Process => write!(f, "process"),
+        }
+    }
+}
+
+/// What scripts run by an interpreter are allowed to do. The default is to
+/// allow nothing.
+///
+/// ```
+/// use rlox::Permissions;
+/// use std::path::Path;
+///
+/// let permissions = Permissions::parse(["fs:read=/data", "env"]).unwrap();
+/// assert!(permissions.can_read(Path::new("/data/input.txt")));
+/// assert!(!permissions.can_read(Path::new("/data/../etc/passwd")));
+/// assert!(!permissions.can_write(Path::new("/data/output.txt")));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Permissions {
+    granted: Vec<Permission>,
+}
+
+impl Permissions {
+    /// Allows nothing.
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    /// Allows everything.
+    pub fn all() -> Self {
+        Self {
+            granted: vec![
+                Permission::Read(None),
+                Permission::Write(None),
+                Permission::Env,
+                Permission::Process,
+            ],
+        }
+    }
+
+    /// Parses a list of entries like `fs:read=/data`.
+    pub fn parse<'a>(entries: impl IntoIterator<Item = &'a str>) -> Result<Self, String> {
+        let mut permissions = Self::none();
+        for entry in entries {
+            permissions.grant(entry.parse()?);
+        }
+        Ok(permissions)
+    }
+
+    pub fn grant(&mut self, permission: Permission) {
+        self.granted.push(permission);
+    }
+
+    pub fn can_read(&self, path: &Path) -> bool {
+        self.readable(path).is_some()
+    }
+
+    pub fn can_write(&self, path: &Path) -> bool {
+        self.writable(path).is_some()
+    }
+
+    /// Works out which file `path` refers to, returning it if it can be read.
+    /// Open the returned path rather than `path`, so that what is opened is
+    /// what was checked.
+    pub fn readable(&self, path: &Path) -> Option<PathBuf> {
+        self.allowed(path, |permission| match permission {
+            Permission::Read(scope) => Some(scope.as_deref()),
+            _ => None,
+        })
+    }
+
+    /// Like [`Permissions::readable`], for writing.
+    pub fn writable(&self, path: &Path) -> Option<PathBuf> {
+        self.allowed(path, |permission| match permission {
+            Permission::Write(scope) => Some(scope.as_deref()),
+            _ => None,
+        })
+    }
+
+    /// Resolves `path` if it's inside the directory of one of the permissions
+    /// `scope` picks out, where `Some(None)` means anywhere.
+    fn allowed<'a>(
+        &'a self,
+        path: &Path,
+        scope: impl Fn(&'a Permission) -> Option<Option<&'a Path>>,
+    ) -> Option<PathBuf> {
+        let resolved = resolve(path);
+        let allowed = self
+            .granted
+            .iter()
+            .filter_map(scope)
+            .any(|scope| match (scope, &resolved) {
+                (None, _) => true,
+                (Some(scope), Some(resolved)) => {
+                    resolve(scope).is_some_and(|scope| resolved.starts_with(scope))
+                }
+                (Some(_), None) => false,
+            });
+        // a path that can't be resolved can only be allowed by a permission
+        // that covers everywhere, and opening it will fail anyway
+        allowed.then(|| resolved.unwrap_or_else(|| path.to_path_buf()))
+    }
+
+    pub fn can_read_env(&self) -> bool {
+        self.granted.contains(&Permission::Env)
+    }
+
+    pub fn can_run_processes(&self) -> bool {
+        self.granted.contains(&Permission::Process)
+    }
+}
+
+/// Finds the absolute path of the file `path` refers to the way the OS does,
+/// following symlinks before going up a `..`, so `link/../secret` is next to
+/// wherever `link` points rather than next to `link`.
+///
+/// Files that are about to be written don't exist yet, so whatever doesn't
+/// exist at the end of `path` is kept as it is. Going up a `..` out of
+/// something that doesn't exist would fail, so it gives `None`.
+fn resolve(path: &Path) -> Option<PathBuf> {
+    let absolute = match std::env::current_dir() {
+        Ok(dir) => dir.join(path),
+        Err(_) => path.to_path_buf(),
+    };
+
+    let mut existing = absolute.as_path();
+    let mut missing = Vec::new();
+    loop {
+        if let Ok(mut resolved) = existing.canonicalize() {
+            resolved.extend(missing.iter().rev());
+            return Some(resolved);
+        }
+        match existing.components().next_back() {
+            Some(Component::Normal(name)) => missing.push(name),
+            _ => return None,
+        }
+        existing = existing.parent()?;
+    }
+}
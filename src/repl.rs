@@ -0,0 +1,217 @@
+use crate::cli::Options;
+use crate::line_editor::LineEditor;
+use rlox::{
+    tokenize, Interpreter, KeywordType, LoxError, Parser, SourceMap, Span, Stmt, TokenType,
+};
+use std::io;
+use std::time::Instant;
+
+const HELP: &str = "\
+Commands:
+    :help           show this message
+    :env            list the variables in the current scope
+    :gc             collect garbage and show what the collector has done
+    :ast <expr>     show the syntax tree of an expression
+    :tokens <code>  show the tokens the scanner produces for some code
+    :load <file>    run a file in this session
+    :reset          forget everything defined so far
+    :time <code>    run some code and report how long it took";
+
+/// An interactive session. Everything defined in it lives as long as the
+/// session does, so later lines can use what earlier lines declared.
+pub struct Repl<'options> {
+    interpreter: Interpreter,
+    editor: LineEditor,
+    options: &'options Options,
+}
+
+impl<'options> Repl<'options> {
+    pub fn new(options: &'options Options) -> Self {
+        Self {
+            interpreter: new_interpreter(options),
+            editor: LineEditor::new(),
+            options,
+        }
+    }
+
+    /// Reads and runs lines until stdin is closed (Ctrl-D).
+    pub fn run(&mut self) -> io::Result<()> {
+        if !self.options.quiet {
+            println!("Rlox");
+        }
+
+        let mut buffer = String::new();
+        loop {
+            let prompt = if buffer.is_empty() { "> " } else { "... " };
+            let Some(line) = self.editor.read_line(prompt, &self.completions())? else {
+                println!();
+                return Ok(());
+            };
+            self.editor.add_history(&line);
+
+            if buffer.is_empty() && line.trim_start().starts_with(':') {
+                self.command(line.trim());
+                continue;
+            }
+            buffer.push_str(&line);
+            buffer.push('\n');
+
+            // keep reading until every bracket and string has been closed
+            if is_incomplete(&buffer) {
+                continue;
+            }
+
+            let input = std::mem::take(&mut buffer);
+            if !input.trim().is_empty() {
+                self.eval(&input, true);
+            }
+        }
+    }
+
+    /// Runs a `:command`.
+    fn command(&mut self, line: &str) {
+        let (command, argument) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let argument = argument.trim();
+
+        match command {
+            ":help" => println!("{HELP}"),
+            ":env" => {
+                for (name, value) in self.interpreter.bindings() {
+                    println!("{name} = {value}");
+                }
+            }
+            ":gc" => {
+                self.interpreter.collect_garbage();
+                println!("{}", self.interpreter.gc_stats());
+            }
+            ":ast" => {
+                let tokens = tokenize(argument, 0);
+                match Parser::new(argument, tokens).parse_expression() {
+                    Ok(expr) => println!("{expr:#?}"),
+                    Err(e) => self.report_in(&e, argument),
+                }
+            }
+            ":tokens" => {
+                for token in tokenize(argument, 0) {
+                    let lexeme = token.as_string(argument);
+                    println!(
+                        "{:?} {lexeme:?} at {}..{}",
+                        token.token_type, token.span.start, token.span.end
+                    );
+                }
+            }
+            ":load" => match std::fs::read_to_string(argument) {
+                Ok(source) => self.eval(&source, false),
+                Err(e) => eprintln!("Couldn't read '{argument}': {e}"),
+            },
+            ":reset" => self.interpreter = new_interpreter(self.options),
+            ":time" => {
+                let start = Instant::now();
+                self.eval(argument, true);
+                println!("took {:?}", start.elapsed());
+            }
+            _ => eprintln!("Unknown command '{command}', try :help"),
+        }
+    }
+
+    /// Runs one complete piece of input. If `echo` is set, the values of
+    /// bare expression statements are printed, like they would be in a
+    /// calculator.
+    pub fn eval(&mut self, input: &str, echo: bool) {
+        let offset = self.interpreter.append_source(&terminate(input));
+
+        let statements = match crate::parse(self.interpreter.source(), offset, self.options) {
+            Ok(statements) => statements,
+            Err(errors) => {
+                for e in errors {
+                    self.report(&e);
+                }
+                return;
+            }
+        };
+
+        for statement in &statements {
+            let result = match statement {
+                Stmt::Expression(stmt) if echo => self
+                    .interpreter
+                    .interpret_expression(&stmt.expression)
+                    .map(|value| println!("{value}")),
+                _ => self.interpreter.interpret(std::slice::from_ref(statement)),
+            };
+
+            if let Err(e) = result {
+                self.report(&e);
+                return;
+            }
+        }
+    }
+
+    /// Keywords and the names of everything currently defined.
+    fn completions(&self) -> Vec<String> {
+        KeywordType::ALL
+            .iter()
+            .map(|keyword| keyword.lexeme().to_string())
+            .chain(
+                self.interpreter
+                    .bindings()
+                    .into_iter()
+                    .map(|(name, _)| name),
+            )
+            .collect()
+    }
+
+    fn report(&self, error: &LoxError) {
+        self.report_in(error, self.interpreter.source());
+    }
+
+    /// Reports an error in code that was never added to the session.
+    fn report_in(&self, error: &LoxError, source: &str) {
+        if !self.options.quiet {
+            error.report(&SourceMap::new("<stdin>", source));
+        }
+    }
+}
+
+fn new_interpreter(options: &Options) -> Interpreter {
+    let mut interpreter = Interpreter::new("", options.permissions.clone());
+    interpreter.set_tracer(options.tracer());
+    interpreter.set_gc_stress(options.gc_stress);
+    interpreter
+        .set_backend(options.backend)
+        .expect("the backend is chosen before anything runs");
+    interpreter
+}
+
+/// Whether `input` has unclosed brackets or strings and needs more lines.
+fn is_incomplete(input: &str) -> bool {
+    let mut depth = 0i32;
+
+    for token in tokenize(input, 0) {
+        match token.token_type {
+            TokenType::LeftParen | TokenType::LeftBrace => depth += 1,
+            TokenType::RightParen | TokenType::RightBrace => depth -= 1,
+            // the scanner returns unterminated strings as unknown tokens
+            TokenType::Unknown if input[token.span.start..].starts_with('"') => return true,
+            _ => {}
+        }
+    }
+
+    depth > 0
+}
+
+/// Adds the `;` that is easy to forget after the last statement of a line.
+fn terminate(input: &str) -> String {
+    let last = tokenize(input, 0)
+        .into_iter()
+        .last()
+        .map(|token| (token.token_type, token.span));
+
+    match last {
+        Some((token_type, Span { end, .. }))
+            if !matches!(token_type, TokenType::Semicolon | TokenType::RightBrace) =>
+        {
+            format!("{};{}", &input[..end], &input[end..])
+        }
+        _ => input.to_string(),
+    }
+}
@@ -0,0 +1,218 @@
+use crate::ast::*;
+use crate::error::LoxError;
+use crate::interner::Symbol;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+/// Works out, before a program runs, which scope every variable refers to.
+///
+/// The tree-walker looks variables up in environments that are created as
+/// the program runs, so without this a closure would see variables declared
+/// after it in an enclosing block. Resolving them up front gives it the same
+/// lexical scoping as the compiler.
+///
+/// The scopes here mirror the environments the interpreter creates: one per
+/// block, one per call holding the parameters and the body, one holding
+/// `this` for every bound method and one holding `super` for the methods of
+/// a subclass.
+pub struct Resolver<'locals> {
+    /// How many environments out from the current one each local variable
+    /// lives, keyed by where its name is in the syntax tree. Programs parsed
+    /// separately can reuse spans, so spans can't tell names apart. Globals
+    /// aren't in here.
+    locals: &'locals mut HashMap<*const Identifier, usize>,
+    scopes: Vec<HashSet<Symbol>>,
+}
+
+impl<'locals> Resolver<'locals> {
+    pub fn new(locals: &'locals mut HashMap<*const Identifier, usize>) -> Self {
+        Self {
+            locals,
+            scopes: Vec::new(),
+        }
+    }
+
+    pub fn resolve(&mut self, statements: &[Stmt]) {
+        for statement in statements {
+            // the parser already reports the mistakes a resolver would catch,
+            // so resolving can't fail
+            let _ = statement.accept(self);
+        }
+    }
+
+    /// Resolves an expression that is evaluated on its own, at the top level.
+    pub fn resolve_expression(&mut self, expr: &Expr) {
+        let _ = self.resolve_expr(expr);
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) -> Result<(), LoxError> {
+        expr.accept(self)
+    }
+
+    fn declare(&mut self, name: Symbol) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name);
+        }
+    }
+
+    /// Records which scope `name` is declared in, if it's a local.
+    fn resolve_local(&mut self, name: &Identifier) {
+        let found = self
+            .scopes
+            .iter()
+            .rev()
+            .position(|scope| scope.contains(&name.symbol));
+        match found {
+            Some(distance) => self.locals.insert(name, distance),
+            // whatever was at this address before may have been a local
+            None => self.locals.remove(&(name as *const Identifier)),
+        };
+    }
+
+    /// Resolves a function's body in a scope along with its parameters, the
+    /// way a call executes it.
+    fn resolve_function(&mut self, function: &FunctionStmt) {
+        self.scopes
+            .push(function.params.iter().map(|param| param.symbol).collect());
+        self.resolve(&function.body);
+        self.scopes.pop();
+    }
+}
+
+impl ExprVisitor<()> for Resolver<'_> {
+    fn visit_assign_expr(&mut self, assign: &AssignExpr) -> Result<(), LoxError> {
+        self.resolve_expr(&assign.value)?;
+        self.resolve_local(&assign.name);
+        Ok(())
+    }
+
+    fn visit_literal_expr(&mut self, _literal: &LiteralExpr) -> Result<(), LoxError> {
+        Ok(())
+    }
+
+    fn visit_logical_expr(&mut self, logical: &LogicalExpr) -> Result<(), LoxError> {
+        self.resolve_expr(&logical.left)?;
+        self.resolve_expr(&logical.right)
+    }
+
+    fn visit_set_expr(&mut self, set: &SetExpr) -> Result<(), LoxError> {
+        self.resolve_expr(&set.value)?;
+        self.resolve_expr(&set.object)
+    }
+
+    // `this` and `super` can't be shadowed, so they are always found in the
+    // nearest scope that has them and don't need resolving
+    fn visit_super_expr(&mut self, _sup: &SuperExpr) -> Result<(), LoxError> {
+        Ok(())
+    }
+
+    fn visit_this_expr(&mut self, _this: &ThisExpr) -> Result<(), LoxError> {
+        Ok(())
+    }
+
+    fn visit_unary_expr(&mut self, unary: &UnaryExpr) -> Result<(), LoxError> {
+        self.resolve_expr(&unary.right)
+    }
+
+    fn visit_binary_expr(&mut self, binary: &BinaryExpr) -> Result<(), LoxError> {
+        self.resolve_expr(&binary.left)?;
+        self.resolve_expr(&binary.right)
+    }
+
+    fn visit_call_expr(&mut self, call: &CallExpr) -> Result<(), LoxError> {
+        self.resolve_expr(&call.callee)?;
+        for argument in &call.arguments {
+            self.resolve_expr(argument)?;
+        }
+        Ok(())
+    }
+
+    fn visit_get_expr(&mut self, get: &GetExpr) -> Result<(), LoxError> {
+        self.resolve_expr(&get.object)
+    }
+
+    fn visit_grouping_expr(&mut self, grouping: &GroupingExpr) -> Result<(), LoxError> {
+        self.resolve_expr(&grouping.expression)
+    }
+
+    fn visit_variable_expr(&mut self, variable: &VariableExpr) -> Result<(), LoxError> {
+        self.resolve_local(&variable.name);
+        Ok(())
+    }
+}
+
+impl StmtVisitor<()> for Resolver<'_> {
+    fn visit_block(&mut self, block: &BlockStmt) -> Result<(), LoxError> {
+        self.scopes.push(HashSet::new());
+        self.resolve(&block.statements);
+        self.scopes.pop();
+        Ok(())
+    }
+
+    fn visit_class(&mut self, stmt: &ClassStmt) -> Result<(), LoxError> {
+        // declared first so that methods can refer to their own class
+        self.declare(stmt.name.symbol);
+
+        if let Some(superclass) = &stmt.superclass {
+            self.visit_variable_expr(superclass)?;
+            self.scopes.push(HashSet::from([Symbol::SUPER]));
+        }
+
+        self.scopes.push(HashSet::from([Symbol::THIS]));
+        for method in &stmt.methods {
+            self.resolve_function(method);
+        }
+        self.scopes.pop();
+
+        if stmt.superclass.is_some() {
+            self.scopes.pop();
+        }
+        Ok(())
+    }
+
+    fn visit_expr(&mut self, stmt: &ExpressionStmt) -> Result<(), LoxError> {
+        self.resolve_expr(&stmt.expression)
+    }
+
+    fn visit_function(&mut self, stmt: &Rc<FunctionStmt>) -> Result<(), LoxError> {
+        // declared before the body so that it can call itself
+        self.declare(stmt.name.symbol);
+        self.resolve_function(stmt);
+        Ok(())
+    }
+
+    fn visit_if(&mut self, stmt: &IfStmt) -> Result<(), LoxError> {
+        self.resolve_expr(&stmt.condition)?;
+        stmt.then_branch.accept(self)?;
+        if let Some(else_branch) = &stmt.else_branch {
+            else_branch.accept(self)?;
+        }
+        Ok(())
+    }
+
+    fn visit_print(&mut self, stmt: &PrintStmt) -> Result<(), LoxError> {
+        self.resolve_expr(&stmt.expression)
+    }
+
+    fn visit_return(&mut self, stmt: &ReturnStmt) -> Result<(), LoxError> {
+        match &stmt.value {
+            Some(value) => self.resolve_expr(value),
+            None => Ok(()),
+        }
+    }
+
+    fn visit_var(&mut self, stmt: &VarStmt) -> Result<(), LoxError> {
+        // the initializer can't see the variable it initializes, so
+        // `var a = a;` copies an outer `a`
+        if let Some(initializer) = &stmt.initializer {
+            self.resolve_expr(initializer)?;
+        }
+        self.declare(stmt.name.symbol);
+        Ok(())
+    }
+
+    fn visit_while(&mut self, stmt: &WhileStmt) -> Result<(), LoxError> {
+        self.resolve_expr(&stmt.condition)?;
+        stmt.body.accept(self)
+    }
+}
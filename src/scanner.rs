@@ -40,7 +40,7 @@ impl<'input> Cursor<'input> {
     }
 
     fn advance_if(&mut self, expected: char) -> bool {
-        if self.peek().unwrap() == expected {
+        if self.peek() == Some(expected) {
             self.advance();
             true
         } else {
@@ -65,11 +65,16 @@ impl<'input> Iterator for Scanner<'input> {
 
 impl<'input> Scanner<'input> {
     pub fn new(source: &'input str) -> Self {
+        Self::with_offset(source, 0)
+    }
+
+    /// Creates a scanner that starts scanning at byte `offset` of `source`.
+    pub fn with_offset(source: &'input str, offset: usize) -> Self {
         Self {
             source,
             cursor: Cursor {
-                chars: source.chars(),
-                byte_pos: 0,
+                chars: source[offset..].chars(),
+                byte_pos: offset,
             },
         }
     }
@@ -77,9 +82,9 @@ impl<'input> Scanner<'input> {
     pub fn scan_token(&mut self) -> Option<Token> {
         let start = self.cursor.byte_pos;
         let token_type = match self.cursor.advance()? {
-            '"' => self.string()?, // string literals
-            '0'..='9' => self.number(start)?,
-            'a'..='z' | 'A'..='Z' => self.identifier_or_keyword(start),
+            '"' => self.string(), // string literals
+            '0'..='9' => self.number(start),
+            'a'..='z' | 'A'..='Z' | '_' => self.identifier_or_keyword(start),
             c if c.is_whitespace() => self.whitespace(),
             '(' => TokenType::LeftParen,
             ')' => TokenType::RightParen,
@@ -136,41 +141,35 @@ impl<'input> Scanner<'input> {
         Some(Token { token_type, span })
     }
 
-    fn string(&mut self) -> Option<TokenType> {
-        while self.cursor.peek()? != '"' {
-            self.cursor.advance();
-        }
-
-        /*
-        if self.cursor.is_at_end() {
-            panic!("unterminated string");
+    fn string(&mut self) -> TokenType {
+        while self.cursor.peek() != Some('"') {
+            // running out of input means the string was never closed
+            if self.cursor.advance().is_none() {
+                return TokenType::Unknown;
+            }
         }
-                */
 
         // the closing "
         self.cursor.advance();
 
-        Some(TokenType::StringLiteral)
+        TokenType::StringLiteral
     }
 
-    fn number(&mut self, _start: usize) -> Option<TokenType> {
-        while self.cursor.peek()?.is_ascii_digit() {
-            self.cursor.advance();
-        }
+    fn number(&mut self, _start: usize) -> TokenType {
+        self.cursor.skip_while(|c| c.is_ascii_digit());
 
-        if self.cursor.peek()? == '.' && self.cursor.peek_two()?.is_ascii_digit() {
+        if self.cursor.peek() == Some('.')
+            && self.cursor.peek_two().is_some_and(|c| c.is_ascii_digit())
+        {
             self.cursor.advance();
-
-            while self.cursor.peek()?.is_ascii_digit() {
-                self.cursor.advance();
-            }
+            self.cursor.skip_while(|c| c.is_ascii_digit());
         }
 
-        Some(TokenType::Number)
+        TokenType::Number
     }
 
     fn identifier_or_keyword(&mut self, start: usize) -> TokenType {
-        self.cursor.skip_while(char::is_alphanumeric);
+        self.cursor.skip_while(|c| c.is_alphanumeric() || c == '_');
 
         let text = &self.source[start..self.cursor.byte_pos];
 
@@ -0,0 +1,46 @@
+/// Maps byte offsets in a source file back to human readable line and
+/// column numbers.
+#[derive(Debug)]
+pub struct SourceMap<'source> {
+    /// The file name shown in diagnostics.
+    pub name: String,
+    source: &'source str,
+    /// The byte offset at which each line starts.
+    line_starts: Vec<usize>,
+}
+
+impl<'source> SourceMap<'source> {
+    pub fn new(name: &str, source: &'source str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        Self {
+            name: name.to_string(),
+            source,
+            line_starts,
+        }
+    }
+
+    /// Returns the 1-based line and column (counted in characters) of `offset`.
+    pub fn location(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.source.len());
+        // the line containing `offset` is the last one that starts at or before it
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let column = self.source[self.line_starts[line]..offset].chars().count();
+
+        (line + 1, column + 1)
+    }
+
+    /// Returns the text of the 1-based `line`, without its line ending.
+    pub fn line(&self, line: usize) -> &'source str {
+        let start = self.line_starts[line - 1];
+        let end = self
+            .line_starts
+            .get(line)
+            .copied()
+            .unwrap_or(self.source.len());
+
+        self.source[start..end].trim_end_matches(['\n', '\r'])
+    }
+}
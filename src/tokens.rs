@@ -1,12 +1,59 @@
+use crate::class::{LoxClass, LoxInstance};
+use crate::foreign::ForeignObject;
+use crate::function::{LoxFunction, NativeFunction};
+use crate::interner::LoxString;
+use crate::vm::{BoundMethod, Closure, VmClass, VmInstance};
+use std::cell::RefCell;
 use std::fmt;
 use std::ops::Range;
+use std::rc::Rc;
 
 #[derive(Debug, Clone)]
 pub enum Object {
     Num(f64),
-    Str(String),
+    /// Strings never change, so they are interned instead of copied.
+    Str(LoxString),
     Nil,
     Bool(bool),
+    Function(Rc<LoxFunction>),
+    Native(Rc<NativeFunction>),
+    Class(Rc<LoxClass>),
+    Instance(Rc<RefCell<LoxInstance>>),
+    /// A value owned by the host, see [`ForeignObject`].
+    Foreign(Rc<RefCell<dyn ForeignObject>>),
+    // the bytecode VM's versions of functions, classes and instances
+    Closure(Rc<Closure>),
+    BoundMethod(Rc<BoundMethod>),
+    VmClass(Rc<VmClass>),
+    VmInstance(Rc<RefCell<VmInstance>>),
+}
+
+impl PartialEq for Object {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Num(a), Self::Num(b)) => a == b,
+            (Self::Str(a), Self::Str(b)) => a == b,
+            (Self::Nil, Self::Nil) => true,
+            (Self::Bool(a), Self::Bool(b)) => a == b,
+            // functions are only equal to themselves
+            (Self::Function(a), Self::Function(b)) => Rc::ptr_eq(a, b),
+            (Self::Native(a), Self::Native(b)) => Rc::ptr_eq(a, b),
+            (Self::Class(a), Self::Class(b)) => Rc::ptr_eq(a, b),
+            (Self::Instance(a), Self::Instance(b)) => Rc::ptr_eq(a, b),
+            (Self::Closure(a), Self::Closure(b)) => Rc::ptr_eq(a, b),
+            (Self::BoundMethod(a), Self::BoundMethod(b)) => Rc::ptr_eq(a, b),
+            (Self::VmClass(a), Self::VmClass(b)) => Rc::ptr_eq(a, b),
+            (Self::VmInstance(a), Self::VmInstance(b)) => Rc::ptr_eq(a, b),
+            (Self::Foreign(a), Self::Foreign(b)) => {
+                Rc::ptr_eq(a, b)
+                    || match (a.try_borrow(), b.try_borrow()) {
+                        (Ok(a), Ok(b)) => a.equals(&*b),
+                        _ => false,
+                    }
+            }
+            _ => false,
+        }
+    }
 }
 
 impl fmt::Display for Object {
@@ -19,6 +66,18 @@ impl fmt::Display for Object {
                 true => write!(f, "true"),
                 false => write!(f, "false"),
             },
+            Self::Function(x) => write!(f, "<fn {}>", x.name),
+            Self::Native(x) => write!(f, "<native fn {}>", x.name),
+            Self::Class(x) => write!(f, "{}", x.name),
+            Self::Instance(x) => write!(f, "{} instance", x.borrow().class.name),
+            Self::Foreign(x) => match x.try_borrow() {
+                Ok(x) => write!(f, "{}", x),
+                Err(_) => write!(f, "<foreign object>"),
+            },
+            Self::Closure(x) => write!(f, "<fn {}>", x.function.name),
+            Self::BoundMethod(x) => write!(f, "<fn {}>", x.method.function.name),
+            Self::VmClass(x) => write!(f, "{}", x.name),
+            Self::VmInstance(x) => write!(f, "{} instance", x.borrow().class.name),
         }
     }
 }
@@ -83,6 +142,50 @@ pub enum KeywordType {
     While,
 }
 
+impl KeywordType {
+    /// Every keyword, in alphabetical order.
+    pub const ALL: [KeywordType; 16] = [
+        KeywordType::And,
+        KeywordType::Class,
+        KeywordType::Else,
+        KeywordType::False,
+        KeywordType::For,
+        KeywordType::Fun,
+        KeywordType::If,
+        KeywordType::Nil,
+        KeywordType::Or,
+        KeywordType::Print,
+        KeywordType::Return,
+        KeywordType::Super,
+        KeywordType::This,
+        KeywordType::True,
+        KeywordType::Var,
+        KeywordType::While,
+    ];
+
+    /// The source text of the keyword.
+    pub fn lexeme(self) -> &'static str {
+        match self {
+            KeywordType::And => "and",
+            KeywordType::Class => "class",
+            KeywordType::Else => "else",
+            KeywordType::False => "false",
+            KeywordType::For => "for",
+            KeywordType::Fun => "fun",
+            KeywordType::If => "if",
+            KeywordType::Nil => "nil",
+            KeywordType::Or => "or",
+            KeywordType::Print => "print",
+            KeywordType::Return => "return",
+            KeywordType::Super => "super",
+            KeywordType::This => "this",
+            KeywordType::True => "true",
+            KeywordType::Var => "var",
+            KeywordType::While => "while",
+        }
+    }
+}
+
 /// A byte range representing a location in a source string.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct Span {
@@ -92,6 +195,16 @@ pub struct Span {
     pub end: usize,
 }
 
+impl Span {
+    /// Returns the smallest span covering both `self` and `other`.
+    pub fn join(self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+}
+
 impl From<Range<usize>> for Span {
     fn from(value: Range<usize>) -> Self {
         Self {
@@ -112,7 +225,7 @@ impl Token {
         let start = self.span.start;
         let end = self.span.end;
 
-        format!("{:?}", &source[start..end])
+        source[start..end].to_string()
     }
 }
 
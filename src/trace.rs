@@ -0,0 +1,252 @@
+use crate::ast::{Expr, Stmt};
+use crate::tokens::{Object, Span};
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+/// How a [`Tracer`] writes out what it sees.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// One line per event, indented by how deeply it is nested:
+    ///
+    /// ```text
+    /// [line 1] var a
+    /// [line 1]   1 + 2 => 3
+    /// [line 1]   define a = 3
+    /// ```
+    #[default]
+    Text,
+    /// One JSON object per line, for tools to read:
+    ///
+    /// ```text
+    /// {"event":"stmt","line":1,"depth":0,"kind":"var","source":"a"}
+    /// {"event":"expr","line":1,"depth":1,"kind":"binary","source":"1 + 2","value":"3"}
+    /// {"event":"define","line":1,"depth":1,"name":"a","value":"3"}
+    /// ```
+    Json,
+}
+
+/// Logs every statement the interpreter executes, every expression it
+/// evaluates along with its value, and every variable it defines.
+///
+/// ```
+/// use rlox::{Interpreter, Permissions, TraceFormat, Tracer};
+///
+/// let mut interpreter = Interpreter::new("", Permissions::none());
+/// // only what happens on lines 10 to 20, as JSON on stderr
+/// interpreter.set_tracer(Some(Tracer::new(TraceFormat::Json).lines(10..=20)));
+/// ```
+pub struct Tracer {
+    format: TraceFormat,
+    lines: Option<RangeInclusive<usize>>,
+    output: Box<dyn Write>,
+    /// How many statements and expressions are being run right now.
+    depth: usize,
+    /// The offset at which each line of the source starts, as far as it has
+    /// been looked at. The source can grow, so this is filled in lazily.
+    line_starts: Vec<usize>,
+    scanned: usize,
+}
+
+impl Tracer {
+    /// Creates a tracer that writes everything to stderr.
+    pub fn new(format: TraceFormat) -> Self {
+        Self {
+            format,
+            lines: None,
+            output: Box::new(io::stderr()),
+            depth: 0,
+            line_starts: vec![0],
+            scanned: 0,
+        }
+    }
+
+    /// Only logs what happens on the given lines.
+    pub fn lines(mut self, lines: RangeInclusive<usize>) -> Self {
+        self.lines = Some(lines);
+        self
+    }
+
+    /// Writes to `output` instead of stderr.
+    pub fn output(mut self, output: impl Write + 'static) -> Self {
+        self.output = Box::new(output);
+        self
+    }
+
+    /// Called before a statement or expression starts running.
+    pub(crate) fn enter(&mut self) {
+        self.depth += 1;
+    }
+
+    /// Called after a statement or expression has finished running.
+    pub(crate) fn exit(&mut self) {
+        self.depth -= 1;
+    }
+
+    /// Logs a statement that is about to run.
+    pub(crate) fn statement(&mut self, source: &str, stmt: &Stmt) {
+        let Some(span) = stmt.span() else {
+            return;
+        };
+        let Some(line) = self.line(source, span) else {
+            return;
+        };
+
+        let (kind, detail) = describe(stmt);
+        let detail = detail.map(|span| &source[span.start..span.end]);
+        let result = match self.format {
+            TraceFormat::Text => match detail {
+                Some(detail) => self.text(line, &format!("{kind} {}", one_line(detail))),
+                None => self.text(line, kind),
+            },
+            TraceFormat::Json => {
+                let mut fields = vec![("kind", json_string(kind))];
+                if let Some(detail) = detail {
+                    fields.push(("source", json_string(detail)));
+                }
+                self.json("stmt", line, &fields)
+            }
+        };
+        // tracing is best effort, it shouldn't stop the program
+        let _ = result;
+    }
+
+    /// Logs an expression that has just been evaluated to `value`.
+    pub(crate) fn expression(&mut self, source: &str, expr: &Expr, value: &Object) {
+        let span = expr.span();
+        let Some(line) = self.line(source, span) else {
+            return;
+        };
+
+        let text = &source[span.start..span.end];
+        let result = match self.format {
+            TraceFormat::Text => self.text(line, &format!("{} => {value}", one_line(text))),
+            TraceFormat::Json => self.json(
+                "expr",
+                line,
+                &[
+                    ("kind", json_string(expression_kind(expr))),
+                    ("source", json_string(text)),
+                    ("value", json_string(&value.to_string())),
+                ],
+            ),
+        };
+        let _ = result;
+    }
+
+    /// Logs a variable being defined in the current scope.
+    pub(crate) fn define(&mut self, source: &str, name: &str, value: &Object, span: Span) {
+        let Some(line) = self.line(source, span) else {
+            return;
+        };
+
+        let result = match self.format {
+            TraceFormat::Text => self.text(line, &format!("define {name} = {value}")),
+            TraceFormat::Json => self.json(
+                "define",
+                line,
+                &[
+                    ("name", json_string(name)),
+                    ("value", json_string(&value.to_string())),
+                ],
+            ),
+        };
+        let _ = result;
+    }
+
+    /// Returns the line `span` starts on, or `None` if it is filtered out.
+    fn line(&mut self, source: &str, span: Span) -> Option<usize> {
+        if source.len() > self.scanned {
+            let scanned = self.scanned;
+            self.line_starts.extend(
+                source[scanned..]
+                    .match_indices('\n')
+                    .map(|(i, _)| scanned + i + 1),
+            );
+            self.scanned = source.len();
+        }
+
+        let line = self
+            .line_starts
+            .partition_point(|&start| start <= span.start);
+        match &self.lines {
+            Some(lines) if !lines.contains(&line) => None,
+            _ => Some(line),
+        }
+    }
+
+    fn text(&mut self, line: usize, message: &str) -> io::Result<()> {
+        let indent = self.depth * 2;
+        writeln!(self.output, "[line {line}] {:indent$}{message}", "")
+    }
+
+    fn json(&mut self, event: &str, line: usize, fields: &[(&str, String)]) -> io::Result<()> {
+        write!(
+            self.output,
+            "{{\"event\":\"{event}\",\"line\":{line},\"depth\":{}",
+            self.depth
+        )?;
+        for (name, value) in fields {
+            write!(self.output, ",\"{name}\":{value}")?;
+        }
+        writeln!(self.output, "}}")
+    }
+}
+
+/// The kind of a statement, and the part of it worth showing.
+fn describe(stmt: &Stmt) -> (&'static str, Option<Span>) {
+    match stmt {
+        Stmt::Block(_) => ("block", None),
+        Stmt::Class(v) => ("class", Some(v.name.span)),
+        Stmt::Expression(v) => ("expression", Some(v.expression.span())),
+        Stmt::Function(v) => ("fun", Some(v.name.span)),
+        Stmt::If(v) => ("if", Some(v.condition.span())),
+        Stmt::Print(v) => ("print", Some(v.expression.span())),
+        Stmt::Return(v) => ("return", v.value.as_ref().map(Expr::span)),
+        Stmt::Var(v) => ("var", Some(v.name.span)),
+        Stmt::While(v) => ("while", Some(v.condition.span())),
+    }
+}
+
+fn expression_kind(expr: &Expr) -> &'static str {
+    match expr {
+        Expr::Assign(_) => "assign",
+        Expr::Binary(_) => "binary",
+        Expr::Call(_) => "call",
+        Expr::Get(_) => "get",
+        Expr::Grouping(_) => "grouping",
+        Expr::Literal(_) => "literal",
+        Expr::Logical(_) => "logical",
+        Expr::Set(_) => "set",
+        Expr::Super(_) => "super",
+        Expr::This(_) => "this",
+        Expr::Unary(_) => "unary",
+        Expr::Variable(_) => "variable",
+    }
+}
+
+/// Squashes code that spans several lines onto one.
+fn one_line(text: &str) -> String {
+    if text.contains('\n') {
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    } else {
+        text.to_string()
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut json = String::with_capacity(text.len() + 2);
+    json.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
@@ -0,0 +1,772 @@
+use crate::chunk::{BinaryOp, Chunk, Function, OpCode};
+use crate::environment::Environment;
+use crate::error::{ErrorType, LoxError};
+use crate::foreign;
+use crate::function::{Arity, Callable};
+use crate::gc::{address, Heap, Trace};
+use crate::interner::{LoxString, Symbol};
+use crate::interpreter::Interpreter;
+use crate::limits::Limit;
+use crate::tokens::{Object, Span};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// A compiled function together with the variables it captured.
+pub struct Closure {
+    pub function: Rc<Function>,
+    upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+/// A captured variable. It stays on the stack while the function that
+/// declared it is running, and moves into the closure when that returns.
+enum Upvalue {
+    Open(usize),
+    Closed(Object),
+}
+
+/// A class declared in code run by the VM.
+pub struct VmClass {
+    pub name: Symbol,
+    methods: RefCell<HashMap<Symbol, Rc<Closure>>>,
+}
+
+pub struct VmInstance {
+    pub class: Rc<VmClass>,
+    fields: HashMap<Symbol, Object>,
+}
+
+/// A method looked up on an instance, remembering the instance as `this`.
+pub struct BoundMethod {
+    receiver: Object,
+    pub method: Rc<Closure>,
+}
+
+// closures and classes can (indirectly) contain themselves, so don't recurse
+impl fmt::Debug for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<fn {}>", self.function.name)
+    }
+}
+
+impl fmt::Debug for VmClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<class {}>", self.name)
+    }
+}
+
+impl fmt::Debug for VmInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<{} instance>", self.class.name)
+    }
+}
+
+impl fmt::Debug for BoundMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<fn {}>", self.method.function.name)
+    }
+}
+
+impl Trace for Closure {
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool {
+        for upvalue in &self.upvalues {
+            visit(address(upvalue));
+        }
+        true
+    }
+
+    // the upvalues are what gets cleared
+    fn clear(&self) {}
+}
+
+impl Trace for RefCell<Upvalue> {
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool {
+        let Ok(upvalue) = self.try_borrow() else {
+            return false;
+        };
+        if let Upvalue::Closed(value) = &*upvalue {
+            value.trace(visit);
+        }
+        true
+    }
+
+    fn clear(&self) {
+        if let Ok(mut upvalue) = self.try_borrow_mut() {
+            if let Upvalue::Closed(value) = &mut *upvalue {
+                *value = Object::Nil;
+            }
+        }
+    }
+}
+
+impl Trace for VmClass {
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool {
+        let Ok(methods) = self.methods.try_borrow() else {
+            return false;
+        };
+        for method in methods.values() {
+            visit(address(method));
+        }
+        true
+    }
+
+    fn clear(&self) {
+        if let Ok(mut methods) = self.methods.try_borrow_mut() {
+            methods.clear();
+        }
+    }
+}
+
+impl Trace for RefCell<VmInstance> {
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool {
+        let Ok(instance) = self.try_borrow() else {
+            return false;
+        };
+        visit(address(&instance.class));
+        for value in instance.fields.values() {
+            value.trace(visit);
+        }
+        true
+    }
+
+    fn clear(&self) {
+        if let Ok(mut instance) = self.try_borrow_mut() {
+            instance.fields.clear();
+        }
+    }
+}
+
+impl Trace for BoundMethod {
+    fn trace(&self, visit: &mut dyn FnMut(usize)) -> bool {
+        self.receiver.trace(visit);
+        visit(address(&self.method));
+        true
+    }
+
+    // the receiver and the method's upvalues are what gets cleared
+    fn clear(&self) {}
+}
+
+/// A function call in progress.
+struct CallFrame {
+    closure: Rc<Closure>,
+    ip: usize,
+    /// Where the function's stack window starts. Slot zero holds the function
+    /// itself, or `this` for methods.
+    slots: usize,
+}
+
+/// The stack of a VM that is waiting for a native to return. Natives can run
+/// more code, which might collect garbage, so the interpreter keeps it where
+/// the collector can find it.
+pub(crate) struct SuspendedVm {
+    stack: Vec<Object>,
+    frames: Vec<CallFrame>,
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+impl SuspendedVm {
+    /// Visits everything on the stack, which are all roots.
+    pub(crate) fn trace(&self, visit: &mut dyn FnMut(usize)) {
+        for value in &self.stack {
+            value.trace(visit);
+        }
+        for frame in &self.frames {
+            visit(address(&frame.closure));
+        }
+        for upvalue in &self.open_upvalues {
+            visit(address(upvalue));
+        }
+    }
+}
+
+/// A stack machine that runs code compiled by [`crate::compiler`].
+///
+/// It shares its globals, natives, limits and permissions with the
+/// interpreter it runs in. Its functions, classes and instances are its own,
+/// which is why an interpreter can't switch backends once code has run.
+pub struct Vm<'interpreter> {
+    interpreter: &'interpreter mut Interpreter,
+    globals: Rc<RefCell<Environment>>,
+    stack: Vec<Object>,
+    frames: Vec<CallFrame>,
+    /// Upvalues that still point into the stack, sorted by slot.
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+impl<'interpreter> Vm<'interpreter> {
+    pub fn new(interpreter: &'interpreter mut Interpreter) -> Self {
+        Self {
+            globals: interpreter.globals(),
+            interpreter,
+            stack: Vec::with_capacity(256),
+            frames: vec![],
+            open_upvalues: vec![],
+        }
+    }
+
+    /// Runs a compiled script, returning the value it returns.
+    pub fn run(&mut self, script: Function) -> Result<Object, LoxError> {
+        let closure = self.interpreter.heap().manage(Closure {
+            function: Rc::new(script),
+            upvalues: vec![],
+        });
+        self.stack.push(Object::Closure(Rc::clone(&closure)));
+        self.frames.push(CallFrame {
+            closure: Rc::clone(&closure),
+            ip: 0,
+            slots: 0,
+        });
+        self.safepoint();
+
+        let result = self.execute(closure);
+        if result.is_err() {
+            self.stack.clear();
+            self.frames.clear();
+            self.open_upvalues.clear();
+        }
+        result
+    }
+
+    fn execute(&mut self, script: Rc<Closure>) -> Result<Object, LoxError> {
+        // the current frame is kept in locals, and written back on calls
+        let mut closure = script;
+        let mut ip = 0;
+        let mut slots = 0;
+
+        loop {
+            let chunk = &closure.function.chunk;
+            let offset = ip;
+            let op = OpCode::try_from(chunk.code[ip]).map_err(|byte| {
+                internal_error(chunk.span_at(offset), &format!("unknown opcode {byte}"))
+            })?;
+            ip += 1;
+
+            match op {
+                OpCode::Constant => {
+                    let constant = read_u16(chunk, &mut ip);
+                    self.stack.push(chunk.constants[constant].clone());
+                }
+                OpCode::Nil => self.stack.push(Object::Nil),
+                OpCode::True => self.stack.push(Object::Bool(true)),
+                OpCode::False => self.stack.push(Object::Bool(false)),
+                OpCode::Pop => {
+                    self.stack.pop();
+                }
+                OpCode::GetLocal => {
+                    let slot = read_u8(chunk, &mut ip);
+                    self.stack.push(self.stack[slots + slot].clone());
+                }
+                OpCode::SetLocal => {
+                    let slot = read_u8(chunk, &mut ip);
+                    self.stack[slots + slot] = self.peek(0).clone();
+                }
+                OpCode::GetGlobal => {
+                    let name = read_name(chunk, &mut ip);
+                    let value = self.globals.borrow().get(name, chunk.span_at(offset))?;
+                    self.stack.push(value);
+                }
+                OpCode::DefineGlobal => {
+                    let name = read_name(chunk, &mut ip);
+                    let span = chunk.span_at(offset);
+                    self.interpreter
+                        .allocate(name.as_str().len() + std::mem::size_of::<Object>(), span)?;
+                    let value = self.pop(span)?;
+                    self.globals.borrow_mut().define(name, value);
+                }
+                OpCode::SetGlobal => {
+                    let name = read_name(chunk, &mut ip);
+                    let value = self.peek(0).clone();
+                    self.globals
+                        .borrow_mut()
+                        .assign(name, value, chunk.span_at(offset))?;
+                }
+                OpCode::GetUpvalue => {
+                    let index = read_u8(chunk, &mut ip);
+                    let value = match &*closure.upvalues[index].borrow() {
+                        Upvalue::Open(slot) => self.stack[*slot].clone(),
+                        Upvalue::Closed(value) => value.clone(),
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::SetUpvalue => {
+                    let index = read_u8(chunk, &mut ip);
+                    let value = self.peek(0).clone();
+                    match &mut *closure.upvalues[index].borrow_mut() {
+                        Upvalue::Open(slot) => self.stack[*slot] = value,
+                        Upvalue::Closed(closed) => *closed = value,
+                    }
+                }
+                OpCode::GetProperty => {
+                    let name = read_name(chunk, &mut ip);
+                    let span = chunk.span_at(offset);
+                    let value = match self.pop(span)? {
+                        Object::VmInstance(instance) => {
+                            let field = instance.borrow().fields.get(&name).cloned();
+                            match field {
+                                Some(value) => value,
+                                None => {
+                                    let class = Rc::clone(&instance.borrow().class);
+                                    bind_method(
+                                        &class,
+                                        Object::VmInstance(instance),
+                                        name,
+                                        span,
+                                        self.interpreter.heap(),
+                                    )?
+                                }
+                            }
+                        }
+                        Object::Foreign(object) => foreign::get(&object, name.as_str(), span)?,
+                        _ => return Err(runtime_error(span, "Only instances have properties.")),
+                    };
+                    self.stack.push(value);
+                    self.safepoint();
+                }
+                OpCode::SetProperty => {
+                    let name = read_name(chunk, &mut ip);
+                    let span = chunk.span_at(offset);
+                    let value = self.pop(span)?;
+                    match self.pop(span)? {
+                        Object::VmInstance(instance) => {
+                            self.interpreter
+                                .allocate(std::mem::size_of::<Object>(), span)?;
+                            instance.borrow_mut().fields.insert(name, value.clone());
+                        }
+                        Object::Foreign(object) => {
+                            foreign::set(&object, name.as_str(), value.clone(), span)?;
+                        }
+                        _ => return Err(runtime_error(span, "Only instances have fields.")),
+                    }
+                    self.stack.push(value);
+                }
+                OpCode::GetSuper => {
+                    let name = read_name(chunk, &mut ip);
+                    let span = chunk.span_at(offset);
+                    let Object::VmClass(superclass) = self.pop(span)? else {
+                        return Err(internal_error(span, "'super' isn't bound to a class"));
+                    };
+                    let receiver = self.pop(span)?;
+                    let method =
+                        bind_method(&superclass, receiver, name, span, self.interpreter.heap())?;
+                    self.stack.push(method);
+                    self.safepoint();
+                }
+                OpCode::Equal => {
+                    let right = self.pop(chunk.span_at(offset))?;
+                    let left = self.pop(chunk.span_at(offset))?;
+                    self.stack.push(Object::Bool(left == right));
+                }
+                OpCode::NotEqual => {
+                    let right = self.pop(chunk.span_at(offset))?;
+                    let left = self.pop(chunk.span_at(offset))?;
+                    self.stack.push(Object::Bool(left != right));
+                }
+                OpCode::Binary => {
+                    let operator =
+                        BinaryOp::try_from(read_u8(chunk, &mut ip) as u8).map_err(|byte| {
+                            internal_error(
+                                chunk.span_at(offset),
+                                &format!("unknown binary operator {byte}"),
+                            )
+                        })?;
+                    let right = self.pop(chunk.span_at(offset))?;
+                    let left = self.pop(chunk.span_at(offset))?;
+                    let value = self.binary(chunk, offset, operator, left, right)?;
+                    self.stack.push(value);
+                }
+                OpCode::Not => match self.pop(chunk.span_at(offset))? {
+                    Object::Bool(x) => self.stack.push(Object::Bool(!x)),
+                    _ => {
+                        return Err(runtime_error(
+                            chunk.span_at(offset),
+                            "Cannot negate non-boolean value",
+                        ))
+                    }
+                },
+                OpCode::Negate => match self.pop(chunk.span_at(offset))? {
+                    Object::Num(n) => self.stack.push(Object::Num(-n)),
+                    _ => {
+                        return Err(runtime_error(
+                            chunk.span_at(offset),
+                            "Cannot negate non-numeric value",
+                        ))
+                    }
+                },
+                OpCode::Print => println!("{}", self.pop(chunk.span_at(offset))?),
+                OpCode::Jump => {
+                    let jump = read_u16(chunk, &mut ip);
+                    ip += jump;
+                }
+                OpCode::JumpIfFalse => {
+                    let jump = read_u16(chunk, &mut ip);
+                    match self.peek(0) {
+                        Object::Bool(true) => {}
+                        Object::Bool(false) => ip += jump,
+                        _ => {
+                            return Err(runtime_error(
+                                chunk.span_at(offset),
+                                "Condition must be a boolean",
+                            ))
+                        }
+                    }
+                }
+                OpCode::Loop => {
+                    let jump = read_u16(chunk, &mut ip);
+                    ip -= jump;
+                    self.interpreter.step(chunk.span_at(offset))?;
+                }
+                OpCode::Call => {
+                    let count = read_u8(chunk, &mut ip);
+                    let paren = chunk.span_at(offset);
+                    let span = chunk.span_at(offset + 1);
+
+                    self.frame(span)?.ip = ip;
+                    if self.call(count, paren, span)? {
+                        // a new frame was pushed, switch to it
+                        let frame = self.frame(span)?;
+                        closure = Rc::clone(&frame.closure);
+                        ip = frame.ip;
+                        slots = frame.slots;
+                    }
+                }
+                OpCode::Closure => {
+                    let index = read_u16(chunk, &mut ip);
+                    let function = Rc::clone(&chunk.functions[index]);
+
+                    let mut upvalues = Vec::with_capacity(function.upvalue_count);
+                    for _ in 0..function.upvalue_count {
+                        let is_local = read_u8(chunk, &mut ip) == 1;
+                        let index = read_u8(chunk, &mut ip);
+                        upvalues.push(if is_local {
+                            self.capture_upvalue(slots + index)
+                        } else {
+                            Rc::clone(&closure.upvalues[index])
+                        });
+                    }
+
+                    self.interpreter
+                        .allocate(std::mem::size_of::<Closure>(), chunk.span_at(offset))?;
+                    let closure = Closure { function, upvalues };
+                    self.stack
+                        .push(Object::Closure(self.interpreter.heap().manage(closure)));
+                    self.safepoint();
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.stack.pop();
+                }
+                OpCode::Return => {
+                    let result = self.pop(chunk.span_at(offset))?;
+                    self.close_upvalues(slots);
+                    self.frames.pop();
+
+                    self.stack.truncate(slots);
+                    let Some(frame) = self.frames.last() else {
+                        return Ok(result);
+                    };
+                    self.stack.push(result);
+                    closure = Rc::clone(&frame.closure);
+                    ip = frame.ip;
+                    slots = frame.slots;
+                }
+                OpCode::Class => {
+                    let name = read_name(chunk, &mut ip);
+                    self.interpreter
+                        .allocate(std::mem::size_of::<VmClass>(), chunk.span_at(offset))?;
+                    let class = VmClass {
+                        name,
+                        methods: RefCell::new(HashMap::new()),
+                    };
+                    self.stack
+                        .push(Object::VmClass(self.interpreter.heap().manage(class)));
+                    self.safepoint();
+                }
+                OpCode::Inherit => {
+                    let span = chunk.span_at(offset);
+                    let Object::VmClass(class) = self.pop(span)? else {
+                        return Err(internal_error(span, "only classes can inherit"));
+                    };
+                    let Object::VmClass(superclass) = self.peek(0) else {
+                        return Err(runtime_error(
+                            chunk.span_at(offset),
+                            "Superclass must be a class.",
+                        ));
+                    };
+                    // classes can't change once declared, so the methods can
+                    // simply be copied down
+                    let methods = superclass.methods.borrow().clone();
+                    class.methods.borrow_mut().extend(methods);
+                }
+                OpCode::Method => {
+                    let name = read_name(chunk, &mut ip);
+                    let span = chunk.span_at(offset);
+                    let Object::Closure(method) = self.pop(span)? else {
+                        return Err(internal_error(span, "methods must be closures"));
+                    };
+                    let Object::VmClass(class) = self.peek(0) else {
+                        return Err(internal_error(span, "methods can only be added to classes"));
+                    };
+                    class.methods.borrow_mut().insert(name, method);
+                }
+            }
+        }
+    }
+
+    /// Pops the value on top of the stack. The compiler makes sure there
+    /// always is one, so running out is a bug in rlox.
+    fn pop(&mut self, span: Span) -> Result<Object, LoxError> {
+        self.stack
+            .pop()
+            .ok_or_else(|| internal_error(span, "the stack is empty"))
+    }
+
+    /// The frame of the function that is running.
+    fn frame(&mut self, span: Span) -> Result<&mut CallFrame, LoxError> {
+        self.frames
+            .last_mut()
+            .ok_or_else(|| internal_error(span, "there is no function running"))
+    }
+
+    fn peek(&self, distance: usize) -> &Object {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+
+    fn binary(
+        &mut self,
+        chunk: &Chunk,
+        offset: usize,
+        operator: BinaryOp,
+        left: Object,
+        right: Object,
+    ) -> Result<Object, LoxError> {
+        if let (BinaryOp::Add, Object::Str(l), Object::Str(r)) = (operator, &left, &right) {
+            self.interpreter
+                .allocate(l.len() + r.len(), chunk.span_at(offset))?;
+            return Ok(Object::Str(LoxString::from(format!("{l}{r}"))));
+        }
+
+        let Object::Num(left) = left else {
+            return Err(runtime_error(
+                chunk.span_at(offset),
+                "Left operand is not a number",
+            ));
+        };
+        let Object::Num(right) = right else {
+            return Err(runtime_error(
+                chunk.span_at(offset + 1),
+                "Right operand is not a number",
+            ));
+        };
+
+        Ok(match operator {
+            BinaryOp::Add => Object::Num(left + right),
+            BinaryOp::Subtract => Object::Num(left - right),
+            BinaryOp::Multiply => Object::Num(left * right),
+            BinaryOp::Divide => Object::Num(left / right),
+            BinaryOp::Greater => Object::Bool(left > right),
+            BinaryOp::GreaterEqual => Object::Bool(left >= right),
+            BinaryOp::Less => Object::Bool(left < right),
+            BinaryOp::LessEqual => Object::Bool(left <= right),
+        })
+    }
+
+    /// Calls the value below the `count` arguments on top of the stack.
+    /// Returns whether a new frame was pushed; natives run straight away and
+    /// leave their result on the stack.
+    fn call(&mut self, count: usize, paren: Span, span: Span) -> Result<bool, LoxError> {
+        let callee_slot = self.stack.len() - 1 - count;
+
+        let (closure, arity) = match &self.stack[callee_slot] {
+            Object::Closure(closure) => (Rc::clone(closure), closure.function.arity),
+            Object::BoundMethod(bound) => {
+                let method = Rc::clone(&bound.method);
+                self.stack[callee_slot] = bound.receiver.clone();
+                let arity = method.function.arity;
+                (method, arity)
+            }
+            Object::VmClass(class) => {
+                let class = Rc::clone(class);
+                let initializer = class.methods.borrow().get(&Symbol::INIT).cloned();
+                self.interpreter
+                    .allocate(std::mem::size_of::<VmInstance>(), span)?;
+                let instance = VmInstance {
+                    class,
+                    fields: HashMap::new(),
+                };
+                let instance = self.interpreter.heap().manage(RefCell::new(instance));
+                self.stack[callee_slot] = Object::VmInstance(instance);
+                self.safepoint();
+
+                match initializer {
+                    Some(initializer) => {
+                        let arity = initializer.function.arity;
+                        (initializer, arity)
+                    }
+                    None => {
+                        check_arity(Arity::Exactly(0), count, paren)?;
+                        self.check_depth(span)?;
+                        self.stack.truncate(callee_slot + 1);
+                        return Ok(false);
+                    }
+                }
+            }
+            Object::Native(native) => {
+                let native = Rc::clone(native);
+                check_arity(native.arity(), count, paren)?;
+                self.check_depth(span)?;
+
+                let arguments = self.stack[callee_slot + 1..].to_vec();
+                let previous = self.interpreter.set_call_span(span);
+                self.suspend();
+                let result = native.call(self.interpreter, arguments);
+                self.resume();
+                self.interpreter.set_call_span(previous);
+                self.stack.truncate(callee_slot);
+                self.stack.push(result?);
+                return Ok(false);
+            }
+            _ => return Err(runtime_error(paren, "Can only call functions and classes.")),
+        };
+
+        check_arity(Arity::Exactly(arity), count, paren)?;
+        self.check_depth(span)?;
+        self.interpreter.step(span)?;
+
+        self.frames.push(CallFrame {
+            closure,
+            ip: 0,
+            slots: callee_slot,
+        });
+        Ok(true)
+    }
+
+    /// Collects garbage if it is time to. Only called once the objects the
+    /// VM has allocated are on its stack, where the collector can find them.
+    fn safepoint(&mut self) {
+        if self.interpreter.heap().wants_collection() {
+            self.suspend();
+            self.interpreter.collect_garbage();
+            self.resume();
+        }
+    }
+
+    /// Hands the stack over to the interpreter, see [`SuspendedVm`].
+    fn suspend(&mut self) {
+        self.interpreter.suspend(SuspendedVm {
+            stack: std::mem::take(&mut self.stack),
+            frames: std::mem::take(&mut self.frames),
+            open_upvalues: std::mem::take(&mut self.open_upvalues),
+        });
+    }
+
+    /// Takes the stack back from the interpreter.
+    fn resume(&mut self) {
+        if let Some(vm) = self.interpreter.resume() {
+            self.stack = vm.stack;
+            self.frames = vm.frames;
+            self.open_upvalues = vm.open_upvalues;
+        }
+    }
+
+    fn check_depth(&self, span: Span) -> Result<(), LoxError> {
+        // the script itself isn't a call
+        let depth = self.frames.len() - 1;
+        match self.interpreter.limits().max_call_depth {
+            Some(max) if depth >= max => Err(LoxError::limit_exceeded(span, Limit::CallDepth)),
+            _ => Ok(()),
+        }
+    }
+
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        let existing = self
+            .open_upvalues
+            .iter()
+            .rev()
+            .find(|upvalue| matches!(*upvalue.borrow(), Upvalue::Open(open) if open == slot));
+        if let Some(upvalue) = existing {
+            return Rc::clone(upvalue);
+        }
+
+        let upvalue = self
+            .interpreter
+            .heap()
+            .manage(RefCell::new(Upvalue::Open(slot)));
+        let index = self.open_upvalues.partition_point(
+            |upvalue| matches!(*upvalue.borrow(), Upvalue::Open(open) if open < slot),
+        );
+        self.open_upvalues.insert(index, Rc::clone(&upvalue));
+        upvalue
+    }
+
+    /// Moves the values of all upvalues pointing at `from` or above off the
+    /// stack and into the upvalues themselves.
+    fn close_upvalues(&mut self, from: usize) {
+        while let Some(upvalue) = self.open_upvalues.last() {
+            let slot = match *upvalue.borrow() {
+                Upvalue::Open(slot) if slot >= from => slot,
+                _ => break,
+            };
+            *upvalue.borrow_mut() = Upvalue::Closed(self.stack[slot].clone());
+            self.open_upvalues.pop();
+        }
+    }
+}
+
+fn read_u8(chunk: &Chunk, ip: &mut usize) -> usize {
+    let byte = chunk.code[*ip];
+    *ip += 1;
+    byte as usize
+}
+
+fn read_u16(chunk: &Chunk, ip: &mut usize) -> usize {
+    let value = u16::from_be_bytes([chunk.code[*ip], chunk.code[*ip + 1]]);
+    *ip += 2;
+    value as usize
+}
+
+fn read_name(chunk: &Chunk, ip: &mut usize) -> Symbol {
+    chunk.names[read_u16(chunk, ip)]
+}
+
+fn bind_method(
+    class: &VmClass,
+    receiver: Object,
+    name: Symbol,
+    span: Span,
+    heap: &mut Heap,
+) -> Result<Object, LoxError> {
+    let method = class.methods.borrow().get(&name).cloned();
+    match method {
+        Some(method) => Ok(Object::BoundMethod(
+            heap.manage(BoundMethod { receiver, method }),
+        )),
+        None => Err(runtime_error(
+            span,
+            &format!("Undefined property '{name}'."),
+        )),
+    }
+}
+
+fn check_arity(arity: Arity, count: usize, paren: Span) -> Result<(), LoxError> {
+    if arity.accepts(count) {
+        return Ok(());
+    }
+    Err(runtime_error(
+        paren,
+        &format!("Expected {arity} arguments but got {count}."),
+    ))
+}
+
+fn runtime_error(span: Span, message: &str) -> LoxError {
+    LoxError::error(span, message, ErrorType::RuntimeError)
+}
+
+fn internal_error(span: Span, message: &str) -> LoxError {
+    LoxError::error(
+        span,
+        &format!("Internal error: {message}."),
+        ErrorType::InternalError,
+    )
+}
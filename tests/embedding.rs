@@ -0,0 +1,48 @@
+//! Drives the interpreter through its public API the way a host program
+//! would, rather than through `Lox` sessions.
+
+use rlox::{tokenize, Backend, Interpreter, Object, Parser, Permissions};
+
+/// Parses and runs `source` as a program of its own, starting at offset 0.
+fn run(interpreter: &mut Interpreter, source: &str) {
+    let statements = Parser::new(source, tokenize(source, 0)).parse().unwrap();
+    interpreter.interpret(&statements).unwrap();
+}
+
+/// Programs parsed separately have overlapping spans, which mustn't make a
+/// name in one resolve like the name at the same place in another.
+#[test]
+fn separately_parsed_programs() {
+    let mut interpreter = Interpreter::new("", Permissions::none());
+
+    run(&mut interpreter, "{ var a = 1; { a = a; } }");
+    run(&mut interpreter, "var a = 2; { { a = a; } }");
+    assert_eq!(interpreter.get_global("a"), Some(Object::Num(2.0)));
+
+    run(
+        &mut interpreter,
+        "fun f() { var b = 3; { return b; } } var result;",
+    );
+    run(&mut interpreter, "var b = 4; { result = f(); }");
+    assert_eq!(interpreter.get_global("result"), Some(Object::Num(3.0)));
+}
+
+/// Each backend has its own functions, classes and instances, so switching
+/// after code has run would leave globals the new backend can't use.
+#[test]
+fn backend_is_fixed_once_code_has_run() {
+    let mut interpreter = Interpreter::new("", Permissions::none());
+    interpreter.set_backend(Backend::Vm).unwrap();
+    interpreter.set_backend(Backend::Tree).unwrap();
+
+    run(
+        &mut interpreter,
+        "class Point { init(x) { this.x = x; } } var p = Point(1);",
+    );
+    assert!(interpreter.set_backend(Backend::Vm).is_err());
+    // choosing the backend it already has is fine
+    interpreter.set_backend(Backend::Tree).unwrap();
+
+    run(&mut interpreter, "var x = Point(2).x + p.x;");
+    assert_eq!(interpreter.get_global("x"), Some(Object::Num(3.0)));
+}
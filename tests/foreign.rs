@@ -0,0 +1,130 @@
+//! Checks how scripts see foreign objects, using a host that registers a
+//! `StringBuilder()` native returning one.
+
+use rlox::{Arity, Backend, ForeignObject, Interpreter, Lox, LoxError, LoxString, Object};
+use std::fmt;
+
+/// Builds up a string piece by piece without copying it on every `+`.
+#[derive(Default)]
+struct StringBuilder {
+    text: String,
+}
+
+impl fmt::Display for StringBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<StringBuilder>")
+    }
+}
+
+impl ForeignObject for StringBuilder {
+    fn type_name(&self) -> &str {
+        "StringBuilder"
+    }
+
+    fn get(&self, name: &str) -> Option<Object> {
+        (name == "length").then(|| Object::Num(self.text.chars().count() as f64))
+    }
+
+    fn method_arity(&self, name: &str) -> Option<Arity> {
+        match name {
+            "append" => Some(Arity::Exactly(1)),
+            "toString" => Some(Arity::Exactly(0)),
+            _ => None,
+        }
+    }
+
+    fn call_method(
+        &mut self,
+        _interpreter: &mut Interpreter,
+        name: &str,
+        arguments: &[Object],
+    ) -> Result<Object, LoxError> {
+        if name == "append" {
+            // strings are appended without their quotes, anything else the
+            // way `print` shows it
+            match &arguments[0] {
+                Object::Str(s) => self.text.push_str(s),
+                value => self.text.push_str(&value.to_string()),
+            }
+            Ok(Object::Nil)
+        } else {
+            Ok(Object::Str(LoxString::new(&self.text)))
+        }
+    }
+}
+
+/// Runs `test` with a session for each backend, in which `builder` is an
+/// empty `StringBuilder`.
+fn with_builder(test: impl Fn(&mut Lox)) {
+    for backend in [Backend::Tree, Backend::Vm] {
+        let mut lox = Lox::new();
+        lox.interpreter().set_backend(backend).unwrap();
+        lox.interpreter()
+            .define_native("StringBuilder", Arity::Exactly(0), |_, _| {
+                Ok(Object::foreign(StringBuilder::default()))
+            });
+        lox.eval("var builder = StringBuilder();").unwrap();
+        test(&mut lox);
+    }
+}
+
+/// The message of the error `source` fails with.
+fn error(lox: &mut Lox, source: &str) -> String {
+    match lox.eval(source) {
+        Ok(value) => panic!("'{source}' gave {value} instead of failing"),
+        Err(errors) => errors[0].message().to_string(),
+    }
+}
+
+#[test]
+fn properties_and_methods() {
+    with_builder(|lox| {
+        assert_eq!(lox.eval("builder;").unwrap().to_string(), "<StringBuilder>");
+
+        lox.eval("builder.append(\"ab\"); builder.append(1); builder.append(nil);")
+            .unwrap();
+        assert_eq!(lox.eval("builder.length;").unwrap(), Object::Num(6.0));
+        assert_eq!(
+            lox.eval("builder.toString();").unwrap(),
+            Object::Str("ab1nil".into())
+        );
+        assert_eq!(
+            lox.eval("builder.append;").unwrap().to_string(),
+            "<native fn append>"
+        );
+    });
+}
+
+#[test]
+fn missing_properties() {
+    with_builder(|lox| {
+        assert_eq!(
+            error(lox, "builder.missing;"),
+            "Undefined property 'missing' on StringBuilder."
+        );
+    });
+}
+
+#[test]
+fn setting_properties() {
+    with_builder(|lox| {
+        assert_eq!(
+            error(lox, "builder.length = 3;"),
+            "Can't set properties on StringBuilder."
+        );
+    });
+}
+
+#[test]
+fn wrong_number_of_arguments() {
+    with_builder(|lox| {
+        assert_eq!(
+            error(lox, "builder.append();"),
+            "Expected 1 arguments but got 0."
+        );
+        assert_eq!(
+            error(lox, "builder.toString(1);"),
+            "Expected 0 arguments but got 1."
+        );
+    });
+}
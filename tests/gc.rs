@@ -0,0 +1,151 @@
+//! Checks that the garbage collector frees cycles, and only cycles.
+
+use rlox::{Arity, Backend, Lox, Object};
+
+#[test]
+fn cycles_are_freed() {
+    let mut lox = Lox::new();
+    lox.eval(
+        "class Node {}
+         fun cycle() {
+           var a = Node();
+           var b = Node();
+           a.next = b;
+           b.next = a;
+           fun f() { return a; }
+           a.f = f;
+         }
+         for (var i = 0; i < 100; i = i + 1) cycle();",
+    )
+    .unwrap();
+
+    let before = lox.interpreter().gc_stats();
+    lox.interpreter().collect_garbage();
+    let after = lox.interpreter().gc_stats();
+
+    // two instances, a function and its environment for every call
+    assert!(after.collected - before.collected >= 400);
+    assert!(after.live < 100, "{after}");
+}
+
+#[test]
+fn reachable_objects_survive() {
+    let mut lox = Lox::new();
+    lox.interpreter().set_gc_stress(true);
+    let value = lox
+        .eval(
+            "class Node {}
+             fun make() {
+               var node = Node();
+               node.self = node;
+               fun name() { return \"made\"; }
+               node.name = name;
+               return node;
+             }
+             var kept = make();
+             make();",
+        )
+        .unwrap();
+
+    // values the host holds on to are only kept while scripts can reach them
+    lox.interpreter().define_global("held", value);
+    lox.eval("kept = nil;").unwrap();
+    lox.interpreter().collect_garbage();
+
+    assert_eq!(
+        lox.eval("held.self.self.name();").unwrap(),
+        Object::Str("made".into())
+    );
+}
+
+/// Locals, arguments and the values of unfinished expressions are only
+/// reachable from the interpreter's own stacks, which have to be roots.
+#[test]
+fn values_in_use_survive() {
+    for backend in [Backend::Tree, Backend::Vm] {
+        let mut lox = Lox::new();
+        lox.interpreter().set_backend(backend).unwrap();
+        lox.interpreter().set_gc_stress(true);
+        lox.interpreter()
+            .define_native("collect", Arity::Exactly(1), |interpreter, arguments| {
+                interpreter.collect_garbage();
+                Ok(arguments[0].clone())
+            });
+
+        let value = lox
+            .eval(
+                "class Node { init(value) { this.value = value; } }
+                 fun churn(node) {
+                   for (var i = 0; i < 10; i = i + 1) Node(i);
+                   return node;
+                 }
+                 fun sum(a, b) { return a.value + b.value; }
+                 fun f() {
+                   var local = Node(1);
+                   return sum(collect(Node(2)), churn(Node(3))) + local.value;
+                 }
+                 var result = sum(Node(4), churn(Node(5))) + f();
+                 result;",
+            )
+            .unwrap();
+        assert_eq!(value, Object::Num(15.0), "{backend:?}");
+    }
+}
+
+/// Runs `source` with each backend, returning how many objects a collection
+/// afterwards freed.
+fn collected_after(source: &str) -> Vec<u64> {
+    [Backend::Tree, Backend::Vm]
+        .into_iter()
+        .map(|backend| {
+            let mut lox = Lox::new();
+            lox.interpreter().set_backend(backend).unwrap();
+            lox.eval(source).unwrap();
+
+            let before = lox.interpreter().gc_stats();
+            lox.interpreter().collect_garbage();
+            lox.interpreter().gc_stats().collected - before.collected
+        })
+        .collect()
+}
+
+#[test]
+fn self_referencing_instances_are_freed() {
+    let collected = collected_after(
+        "class Node {}
+         fun make() {
+           var node = Node();
+           node.self = node;
+         }
+         for (var i = 0; i < 100; i = i + 1) make();",
+    );
+    for collected in collected {
+        assert!(collected >= 100, "only {collected} freed");
+    }
+}
+
+#[test]
+fn closures_stored_in_their_own_environment_are_freed() {
+    let collected = collected_after(
+        "fun make() {
+           var f;
+           fun g() { return f; }
+           f = g;
+         }
+         for (var i = 0; i < 100; i = i + 1) make();",
+    );
+    for collected in collected {
+        assert!(collected >= 100, "only {collected} freed");
+    }
+}
+
+#[test]
+fn strings_are_counted_until_freed() {
+    let mut lox = Lox::new();
+    lox.eval("var s = \"garbage\" + \" collected\";").unwrap();
+    let before = lox.interpreter().gc_stats().strings;
+
+    lox.eval("s = nil;").unwrap();
+    lox.interpreter().collect_garbage();
+    assert_eq!(lox.interpreter().gc_stats().strings, before - 1);
+}
@@ -0,0 +1,279 @@
+//! Runs every `.lox` file under `tests/` and checks what it does against the
+//! annotations in its comments, in the style of the reference Lox test suite:
+//!
+//! ```text
+//! print 1 + 2; // expect: 3
+//! print nil + 1; // expect runtime error: Left operand is not a number
+//! var = 1; // Error at 'var': Expect variable name.
+//! // [line 7] Error: Expect '}' after block.
+//! ```
+//!
+//! A file with compile errors must exit with 65 and one with a runtime error
+//! with 70. Everything printed to stdout must be expected, in order. Every
+//! file is run with both the tree-walking and the bytecode backend, and
+//! again with the garbage collector running on every allocation.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// What a test file expects to happen when it is run.
+#[derive(Default)]
+struct Expectations {
+    output: Vec<String>,
+    compile_errors: Vec<ExpectedError>,
+    runtime_error: Option<ExpectedError>,
+}
+
+struct ExpectedError {
+    line: usize,
+    /// The lexeme the error should point at, or `None` for `Error at end` and
+    /// errors that don't say.
+    at: Option<String>,
+    message: String,
+}
+
+/// An error as reported on stderr.
+struct ReportedError {
+    line: usize,
+    column: usize,
+    message: String,
+}
+
+impl Expectations {
+    fn parse(source: &str) -> Self {
+        let mut expectations = Self::default();
+
+        for (index, text) in source.lines().enumerate() {
+            let Some((_, comment)) = text.split_once("// ") else {
+                continue;
+            };
+
+            if let Some(output) = comment.strip_prefix("expect: ") {
+                expectations.output.push(output.to_string());
+            } else if let Some(message) = comment.strip_prefix("expect runtime error: ") {
+                expectations.runtime_error = Some(ExpectedError {
+                    line: index + 1,
+                    at: None,
+                    message: message.to_string(),
+                });
+            } else if let Some(error) = parse_compile_error(comment, index + 1) {
+                expectations.compile_errors.push(error);
+            }
+        }
+
+        expectations
+    }
+
+    fn exit_code(&self) -> i32 {
+        if !self.compile_errors.is_empty() {
+            65
+        } else if self.runtime_error.is_some() {
+            70
+        } else {
+            0
+        }
+    }
+}
+
+/// Parses `[line N] Error at 'x': message`, where the `[line N]` and the
+/// `at ...` parts are optional.
+fn parse_compile_error(comment: &str, line: usize) -> Option<ExpectedError> {
+    let (line, rest) = match comment.strip_prefix("[line ") {
+        Some(rest) => {
+            let (number, rest) = rest.split_once("] ")?;
+            (number.parse().ok()?, rest)
+        }
+        None => (line, comment),
+    };
+
+    let rest = rest.strip_prefix("Error")?;
+    let (location, message) = rest.split_once(": ")?;
+    let at = match location {
+        "" | " at end" => None,
+        location => Some(
+            location
+                .strip_prefix(" at '")?
+                .strip_suffix('\'')?
+                .to_string(),
+        ),
+    };
+
+    Some(ExpectedError {
+        line,
+        at,
+        message: message.to_string(),
+    })
+}
+
+/// Picks the errors out of what the interpreter printed to stderr:
+///
+/// ```text
+/// error: Undefined variable 'b'.
+///  --> tests/variables/undefined.lox:6:11
+/// ```
+fn parse_stderr(stderr: &str) -> Vec<ReportedError> {
+    let mut errors = Vec::new();
+    let mut lines = stderr.lines();
+
+    while let Some(text) = lines.next() {
+        let Some(message) = text.strip_prefix("error: ") else {
+            continue;
+        };
+        let location = lines
+            .next()
+            .and_then(|text| text.trim_start().strip_prefix("--> "))
+            .and_then(|location| {
+                let mut parts = location.rsplitn(3, ':');
+                let column = parts.next()?.parse().ok()?;
+                let line = parts.next()?.parse().ok()?;
+                Some((line, column))
+            });
+
+        if let Some((line, column)) = location {
+            errors.push(ReportedError {
+                line,
+                column,
+                message: message.to_string(),
+            });
+        }
+    }
+
+    errors
+}
+
+/// Checks that `reported` is the error that was `expected`, appending what is
+/// wrong to `failures` if it isn't.
+fn check_error(
+    source: &str,
+    expected: &ExpectedError,
+    reported: &ReportedError,
+    failures: &mut String,
+) {
+    if expected.line != reported.line || expected.message != reported.message {
+        let _ = writeln!(
+            failures,
+            "expected error on line {}: {}\n     got error on line {}: {}",
+            expected.line, expected.message, reported.line, reported.message
+        );
+        return;
+    }
+
+    if let Some(at) = &expected.at {
+        let text = source.lines().nth(reported.line - 1).unwrap_or("");
+        let found: String = text.chars().skip(reported.column - 1).collect();
+        if !found.starts_with(at.as_str()) {
+            let _ = writeln!(
+                failures,
+                "expected error on line {} at '{at}', but it points at '{found}'",
+                expected.line
+            );
+        }
+    }
+}
+
+/// Runs one test file with the given options, returning a description of
+/// everything that went differently from what it expects.
+fn run(path: &Path, options: &[&str]) -> Result<(), String> {
+    let source = std::fs::read_to_string(path).map_err(|e| format!("couldn't read: {e}"))?;
+    let expectations = Expectations::parse(&source);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .args(options)
+        .arg(path)
+        .output()
+        .map_err(|e| format!("couldn't run rlox: {e}"))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    let mut failures = String::new();
+
+    let actual: Vec<&str> = stdout.lines().collect();
+    if actual != expectations.output {
+        let _ = writeln!(
+            failures,
+            "expected output:\n{}\ngot:\n{}",
+            expectations.output.join("\n"),
+            actual.join("\n")
+        );
+    }
+
+    let reported = parse_stderr(&stderr);
+    let expected: Vec<&ExpectedError> = match &expectations.runtime_error {
+        Some(error) => vec![error],
+        None => expectations.compile_errors.iter().collect(),
+    };
+    if expected.len() != reported.len() {
+        let _ = writeln!(
+            failures,
+            "expected {} errors, got {}:\n{stderr}",
+            expected.len(),
+            reported.len()
+        );
+    } else {
+        for (expected, reported) in expected.iter().zip(&reported) {
+            check_error(&source, expected, reported, &mut failures);
+        }
+    }
+
+    let code = output.status.code();
+    if code != Some(expectations.exit_code()) {
+        let _ = writeln!(
+            failures,
+            "expected exit code {}, got {code:?}",
+            expectations.exit_code()
+        );
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(failures)
+    }
+}
+
+fn find_tests(dir: &Path, tests: &mut Vec<PathBuf>) {
+    let entries = std::fs::read_dir(dir).expect("couldn't read the tests directory");
+    for entry in entries {
+        let path = entry.expect("couldn't read the tests directory").path();
+        if path.is_dir() {
+            find_tests(&path, tests);
+        } else if path.extension().is_some_and(|extension| extension == "lox") {
+            tests.push(path);
+        }
+    }
+}
+
+#[test]
+fn golden() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let mut tests = Vec::new();
+    find_tests(&root, &mut tests);
+    tests.sort();
+    assert!(!tests.is_empty(), "no tests found in {}", root.display());
+
+    // run everything so that one failure doesn't hide the others
+    let configurations: [&[&str]; 4] = [
+        &["--backend=tree"],
+        &["--backend=vm"],
+        &["--backend=tree", "--gc-stress"],
+        &["--backend=vm", "--gc-stress"],
+    ];
+    let mut failed = 0;
+    for options in configurations {
+        for path in &tests {
+            if let Err(failures) = run(path, options) {
+                failed += 1;
+                let name = path.strip_prefix(&root).unwrap_or(path);
+                eprintln!(
+                    "FAIL {} ({})\n{failures}",
+                    name.display(),
+                    options.join(" ")
+                );
+            }
+        }
+    }
+
+    let runs = tests.len() * configurations.len();
+    assert!(failed == 0, "{failed} of {runs} tests failed");
+}
@@ -0,0 +1,99 @@
+//! Checks that scripts stop with the right error when they run into each of
+//! the resource limits, whichever backend runs them.
+
+use rlox::{Backend, ErrorType, Limit, Limits, Lox};
+use std::time::Duration;
+
+/// Runs `source` under `limits` with each backend, checking that it fails
+/// because of `limit`.
+fn assert_exceeds(limits: Limits, source: &str, limit: Limit) {
+    for backend in [Backend::Tree, Backend::Vm] {
+        let mut lox = Lox::new();
+        lox.interpreter().set_backend(backend).unwrap();
+        lox.interpreter().set_limits(limits.clone());
+
+        let errors = match lox.eval(source) {
+            Ok(value) => panic!("{backend:?} finished with {value} instead of hitting {limit:?}"),
+            Err(errors) => errors,
+        };
+        assert_eq!(
+            errors[0].error_type(),
+            &ErrorType::LimitExceeded(limit),
+            "{backend:?}: {}",
+            errors[0].message()
+        );
+    }
+}
+
+#[test]
+fn steps() {
+    let limits = Limits {
+        max_steps: Some(10_000),
+        ..Limits::default()
+    };
+    assert_exceeds(limits, "while (true) {}", Limit::Steps);
+}
+
+#[test]
+fn call_depth() {
+    let limits = Limits {
+        max_call_depth: Some(50),
+        ..Limits::default()
+    };
+    assert_exceeds(limits, "fun f() { f(); } f();", Limit::CallDepth);
+}
+
+#[test]
+fn call_depth_through_nested_blocks() {
+    let limits = Limits {
+        max_call_depth: Some(50),
+        ..Limits::default()
+    };
+    let nested = (0..12).fold("return f();".to_string(), |body, _| {
+        format!("{{ if (true) {{ {body} }} }}")
+    });
+    assert_exceeds(
+        limits,
+        &format!("fun f() {{ {nested} }} f();"),
+        Limit::CallDepth,
+    );
+}
+
+#[test]
+fn memory() {
+    let limits = Limits {
+        max_memory: Some(100_000),
+        ..Limits::default()
+    };
+    assert_exceeds(
+        limits,
+        "var s = \"x\"; while (true) { s = s + s; }",
+        Limit::Memory,
+    );
+}
+
+#[test]
+fn timeout() {
+    let limits = Limits {
+        timeout: Some(Duration::from_millis(50)),
+        ..Limits::default()
+    };
+    assert_exceeds(limits, "while (true) {}", Limit::Time);
+}
+
+#[test]
+fn timeout_starts_with_each_run() {
+    for backend in [Backend::Tree, Backend::Vm] {
+        let mut lox = Lox::new();
+        lox.interpreter().set_backend(backend).unwrap();
+        lox.interpreter().set_limits(Limits {
+            timeout: Some(Duration::from_millis(50)),
+            ..Limits::default()
+        });
+
+        // the time spent before running doesn't count against the script
+        std::thread::sleep(Duration::from_millis(100));
+        let result = lox.eval("for (var i = 0; i < 2000; i = i + 1) {}");
+        assert!(result.is_ok(), "{backend:?}");
+    }
+}
@@ -0,0 +1,97 @@
+//! Checks that programs survive being saved as `.loxc` files, and that
+//! broken files are rejected without panicking.
+
+use rlox::{tokenize, Parser, Program};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Every test program that parses, encoded.
+fn encoded_programs() -> Vec<(PathBuf, Vec<u8>)> {
+    let mut programs = Vec::new();
+    let mut dirs = vec![Path::new(env!("CARGO_MANIFEST_DIR")).join("tests")];
+
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|extension| extension == "lox") {
+                let source = std::fs::read_to_string(&path).unwrap();
+                if let Ok(statements) = Parser::new(&source, tokenize(&source, 0)).parse() {
+                    let bytes = Program::encode(&source, &statements);
+                    programs.push((path, bytes));
+                }
+            }
+        }
+    }
+
+    assert!(!programs.is_empty());
+    programs
+}
+
+#[test]
+fn round_trip() {
+    for (path, bytes) in encoded_programs() {
+        let program = Program::decode(&bytes)
+            .unwrap_or_else(|e| panic!("couldn't decode {}: {e}", path.display()));
+        let again = Program::encode(&program.source, &program.statements);
+        assert!(again == bytes, "{} changed in a round trip", path.display());
+    }
+}
+
+#[test]
+fn truncated_files_are_rejected() {
+    for (path, bytes) in encoded_programs() {
+        for len in 0..bytes.len() {
+            assert!(
+                Program::decode(&bytes[..len]).is_err(),
+                "{} cut to {len} bytes was accepted",
+                path.display()
+            );
+        }
+    }
+}
+
+#[test]
+fn corrupt_files_dont_panic() {
+    for (_, bytes) in encoded_programs() {
+        for index in 0..bytes.len() {
+            for flip in [0x01, 0x10, 0x80, 0xff] {
+                let mut corrupt = bytes.clone();
+                corrupt[index] ^= flip;
+                // anything goes, as long as it doesn't panic
+                let _ = Program::decode(&corrupt);
+            }
+        }
+    }
+}
+
+#[test]
+fn compile_and_run() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let script = root.join("inheritance/super.lox");
+    let output = std::env::temp_dir().join(format!("rlox-{}.loxc", std::process::id()));
+
+    let compile = Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .arg("compile")
+        .arg(&script)
+        .arg("-o")
+        .arg(&output)
+        .status()
+        .unwrap();
+    assert!(compile.success());
+
+    let run = |path: &Path| {
+        Command::new(env!("CARGO_BIN_EXE_rlox"))
+            .arg("run")
+            .arg(path)
+            .output()
+            .unwrap()
+    };
+    let from_source = run(&script);
+    let compiled = run(&output);
+    std::fs::remove_file(&output).unwrap();
+
+    assert!(compiled.status.success());
+    assert_eq!(compiled.stdout, from_source.stdout);
+}
@@ -0,0 +1,90 @@
+//! Checks that scripts can only reach the files their permissions cover,
+//! however the paths they pass are spelled.
+
+use rlox::{Lox, Object, Permissions};
+use std::fs;
+use std::path::PathBuf;
+
+/// A directory scripts are allowed to read and write, next to one they
+/// aren't, with a symlink from inside the first to a directory inside the
+/// second.
+struct Sandbox {
+    root: PathBuf,
+}
+
+impl Sandbox {
+    fn new(name: &str) -> Self {
+        let root = std::env::temp_dir().join(format!("rlox-{name}-{}", std::process::id()));
+        fs::create_dir_all(root.join("allowed")).unwrap();
+        fs::create_dir_all(root.join("outside/sub")).unwrap();
+        fs::write(root.join("allowed/secret.txt"), "allowed").unwrap();
+        fs::write(root.join("outside/secret.txt"), "outside").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(root.join("outside/sub"), root.join("allowed/link")).unwrap();
+        Self { root }
+    }
+
+    fn path(&self, path: &str) -> String {
+        self.root.join(path).display().to_string()
+    }
+
+    fn lox(&self) -> Lox {
+        let allowed = self.path("allowed");
+        let permissions = Permissions::parse([
+            &*format!("fs:read={allowed}"),
+            &format!("fs:write={allowed}"),
+        ])
+        .unwrap();
+        Lox::with_permissions(permissions)
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+#[test]
+fn files_inside_the_directory() {
+    let sandbox = Sandbox::new("inside");
+    let mut lox = sandbox.lox();
+
+    let path = sandbox.path("allowed/secret.txt");
+    let contents = lox.eval(&format!("readFile({path:?});")).unwrap();
+    assert_eq!(contents, Object::Str("allowed".into()));
+
+    let path = sandbox.path("allowed/new.txt");
+    lox.eval(&format!("writeFile({path:?}, \"new\");")).unwrap();
+    assert_eq!(fs::read_to_string(path).unwrap(), "new");
+}
+
+#[test]
+fn parent_directories_outside() {
+    let sandbox = Sandbox::new("parent");
+    let mut lox = sandbox.lox();
+
+    let path = sandbox.path("allowed/../outside/secret.txt");
+    let errors = lox.eval(&format!("readFile({path:?});")).unwrap_err();
+    assert!(errors[0].message().starts_with("Permission denied"));
+}
+
+/// `allowed/link/..` is `outside`, not `allowed`, because the OS follows the
+/// symlink before going up.
+#[cfg(unix)]
+#[test]
+fn parent_directories_of_symlinks() {
+    let sandbox = Sandbox::new("symlink");
+    let mut lox = sandbox.lox();
+
+    let path = sandbox.path("allowed/link/../secret.txt");
+    let errors = lox.eval(&format!("readFile({path:?});")).unwrap_err();
+    assert!(errors[0].message().starts_with("Permission denied"));
+
+    let path = sandbox.path("allowed/link/../new.txt");
+    let errors = lox
+        .eval(&format!("writeFile({path:?}, \"new\");"))
+        .unwrap_err();
+    assert!(errors[0].message().starts_with("Permission denied"));
+    assert!(!sandbox.root.join("outside/new.txt").exists());
+}
@@ -0,0 +1,76 @@
+//! Checks what `--trace` logs while running `tests/trace/program.lox`.
+
+use std::path::Path;
+use std::process::Command;
+
+fn trace(args: &[&str]) -> String {
+    let program = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/trace/program.lox");
+    let output = Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .args(args)
+        .arg(program)
+        .output()
+        .expect("couldn't run rlox");
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "6\n");
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn text() {
+    let expected = "\
+[line 1] var a
+[line 1]     1 => 1
+[line 1]     2 => 2
+[line 1]   1 + 2 => 3
+[line 1]   define a = 3
+[line 2] fun double
+[line 2]   define double = <fn double>
+[line 5] print double(a)
+[line 5]     double => <fn double>
+[line 5]     a => 3
+[line 2]     define x = 3
+[line 3]     return x * 2
+[line 3]         x => 3
+[line 3]         2 => 2
+[line 3]       x * 2 => 6
+[line 5]   double(a) => 6
+";
+    assert_eq!(trace(&["--trace"]), expected);
+}
+
+#[test]
+fn json_lines_in_range() {
+    let expected = r#"{"event":"stmt","line":3,"depth":2,"kind":"return","source":"x * 2"}
+{"event":"expr","line":3,"depth":4,"kind":"variable","source":"x","value":"3"}
+{"event":"expr","line":3,"depth":4,"kind":"literal","source":"2","value":"2"}
+{"event":"expr","line":3,"depth":3,"kind":"binary","source":"x * 2","value":"6"}
+"#;
+    assert_eq!(trace(&["--trace=json", "--trace-lines=3-4"]), expected);
+}
+
+#[test]
+fn rejected_with_the_vm() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .args(["--backend=vm", "--trace"])
+        .arg(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/trace/program.lox"))
+        .output()
+        .expect("couldn't run rlox");
+
+    assert_eq!(output.status.code(), Some(64));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .starts_with("rlox: tracing only works with '--backend=tree'"));
+}
+
+/// `--verbose` still dumps the tokens and syntax tree, it just can't trace.
+#[test]
+fn verbose_with_the_vm() {
+    for args in [["--verbose", "--backend=vm"], ["--backend=vm", "--verbose"]] {
+        let log = trace(&args);
+        assert!(log.starts_with("Token {"), "{log}");
+        assert!(log.contains("Print("), "{log}");
+        assert!(!log.contains("[line "), "{log}");
+    }
+    assert!(trace(&["--verbose"]).contains("[line 5]   double(a) => 6"));
+}